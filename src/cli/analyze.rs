@@ -7,8 +7,11 @@ use rand::seq::SliceRandom;
 
 use water_sort_puzzle_solver::*;
 
+use super::budget::BudgetArgs;
+
 #[derive(Clone)]
 struct Stat {
+    outcome: SearchOutcome,
     moves: Option<usize>,
     duration: f64,
 }
@@ -30,6 +33,9 @@ pub struct AnalyzerArgs {
     /// Number of runs to perform.
     #[arg(short = 'n', long, value_parser, default_value_t = 1000)]
     runs: usize,
+
+    #[command(flatten)]
+    budget: BudgetArgs,
 }
 
 pub fn run_analyzer(subcommand: &AnalyzerArgs) {
@@ -37,6 +43,7 @@ pub fn run_analyzer(subcommand: &AnalyzerArgs) {
     let height: usize = subcommand.height;
     let tube_count: usize = subcommand.tubes.unwrap_or(colors + 2);
     let runs = subcommand.runs;
+    let config = subcommand.budget.to_config();
 
     let stats: Vec<Stat> = (0..runs)
         .map(|_| {
@@ -47,19 +54,35 @@ pub fn run_analyzer(subcommand: &AnalyzerArgs) {
             tubes.shuffle(&mut rng);
             tubes.resize(tube_count * height, 0);
             let mut solver = BFSSolver::new(height, tubes);
+            solver.set_config(config.clone());
             let now = time::Instant::now();
-            let moves = if solver.search() {
+            let outcome = solver.search();
+            let moves = if outcome.is_solved() {
                 Some(solver.get_solution().len())
             } else {
                 None
             };
             let duration = now.elapsed().as_secs_f64();
-            Stat { moves, duration }
+            Stat {
+                outcome,
+                moves,
+                duration,
+            }
         })
         .collect();
 
     let solvable_moves: Vec<usize> = stats.iter().filter_map(|s| s.moves).collect();
     let solvable_count = solvable_moves.len();
+    let interrupted_count = stats
+        .iter()
+        .filter(|s| matches!(s.outcome, SearchOutcome::BudgetExceeded { .. }))
+        .count();
+    if interrupted_count > 0 {
+        println!(
+            "{}% exceeded the search budget.",
+            (interrupted_count * 100) as f64 / runs as f64
+        );
+    }
 
     if solvable_count == 0 {
         println!("0% solvable.");
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use clap::Args;
+
+use water_sort_puzzle_solver::*;
+
+#[derive(Args)]
+pub struct BudgetArgs {
+    /// Time limit of each search in seconds
+    #[arg(long, value_parser)]
+    pub timeout: Option<f64>,
+
+    /// Maximum number of visited states of each search
+    #[arg(long, value_parser)]
+    pub max_states: Option<usize>,
+}
+
+impl BudgetArgs {
+    pub fn to_config(&self) -> SearchConfig {
+        SearchConfig {
+            max_duration: self.timeout.map(Duration::from_secs_f64),
+            max_visited_states: self.max_states,
+            ..SearchConfig::default()
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use clap::Args;
+
+use water_sort_puzzle_solver::*;
+
+use super::budget::BudgetArgs;
+use super::input::{InputError, read_tubes};
+
+#[derive(Args)]
+pub struct HintArgs {
+    /// Number of colors
+    #[arg(short, long, value_parser)]
+    pub colors: usize,
+
+    /// Height of each tube
+    #[arg(short = 'H', long, value_parser)]
+    pub height: usize,
+
+    /// Number of tubes (default: colors + 2)
+    #[arg(short, long, value_parser)]
+    pub tubes: Option<usize>,
+
+    #[command(flatten)]
+    pub budget: BudgetArgs,
+}
+
+pub fn run_hint(subcommand: &HintArgs) -> Result<(), InputError> {
+    let color_count: usize = subcommand.colors;
+    let height: usize = subcommand.height;
+    let tube_count: usize = subcommand.tubes.unwrap_or(color_count + 2);
+    let hint = if color_count <= u8::MAX_COLOR {
+        let tubes: Vec<u8> = read_tubes("hint", color_count, height, tube_count)?;
+        get_hint(height, tubes, subcommand.budget.to_config())
+    } else {
+        let tubes: Vec<u16> = read_tubes("hint", color_count, height, tube_count)?;
+        get_hint(height, tubes, subcommand.budget.to_config())
+    };
+
+    match hint {
+        Hint::Solved => println!("Already solved."),
+        Hint::Move {
+            step,
+            remaining,
+            optimal,
+        } => {
+            println!("{} -> {}", step.from + 1, step.to + 1);
+            if optimal {
+                println!("Moves remaining: {remaining}");
+            } else {
+                println!("Moves remaining: at most {remaining}");
+            }
+        }
+        Hint::Unsolvable => println!("No solution."),
+        Hint::Unknown(SearchOutcome::BudgetExceeded { reason }) => {
+            println!("Search stopped: {reason}.")
+        }
+        Hint::Unknown(_) => println!("Search cancelled."),
+    }
+    Ok(())
+}
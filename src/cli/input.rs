@@ -0,0 +1,446 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, stdin};
+
+use rustc_hash::FxHashMap;
+
+use water_sort_puzzle_solver::*;
+
+/// Why the puzzle, the moves given on stdin or the options were rejected.
+#[derive(Debug)]
+pub enum InputError {
+    Io(io::Error),
+    TooManyColors {
+        colors: usize,
+        max: usize,
+    },
+    ColorCount {
+        expected: usize,
+        actual: usize,
+    },
+    ColorCells {
+        color: String,
+        expected: usize,
+        actual: usize,
+    },
+    Puzzle(PuzzleError),
+    InvalidMove(String),
+    InvalidCapacity(String),
+    InvalidConstraint(String),
+    UnsupportedRules,
+    UnsupportedCapacities,
+    /// Tube constraints were given to the named algorithm, option or command.
+    UnsupportedConstraints(String),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Io(error) => write!(f, "{error}"),
+            InputError::TooManyColors { colors, max } => {
+                write!(f, "Too many colors: {colors}, at most {max} are supported")
+            }
+            InputError::ColorCount { expected, actual } => write!(
+                f,
+                "Number of colors mismatch: expected {expected}, actual {actual}"
+            ),
+            InputError::ColorCells {
+                color,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Color {color} count mismatch: expected {expected}, actual {actual}"
+            ),
+            InputError::Puzzle(error) => write!(f, "Invalid puzzle: {error}"),
+            InputError::InvalidMove(line) => write!(f, "Invalid move: {line}"),
+            InputError::InvalidCapacity(token) => write!(f, "Invalid tube capacity: {token}"),
+            InputError::InvalidConstraint(token) => write!(f, "Invalid tube constraint: {token}"),
+            InputError::UnsupportedCapacities => write!(
+                f,
+                "Tube capacities are only supported by single-threaded bfs and dfs, and verify"
+            ),
+            InputError::UnsupportedConstraints(name) => write!(
+                f,
+                "Tube constraints are not supported by {name}, only by single-threaded bfs and \
+                 dfs, and verify"
+            ),
+            InputError::UnsupportedRules => write!(
+                f,
+                "Single-unit rules are only supported by single-threaded bfs and dfs"
+            ),
+        }
+    }
+}
+
+impl Error for InputError {}
+
+impl From<io::Error> for InputError {
+    fn from(error: io::Error) -> Self {
+        InputError::Io(error)
+    }
+}
+
+impl From<PuzzleError> for InputError {
+    fn from(error: PuzzleError) -> Self {
+        InputError::Puzzle(error)
+    }
+}
+
+/// Tubes read from stdin, with their capacities and constraints when any line declared one.
+pub struct Tubes<C: Cell> {
+    pub height: usize,
+    pub cells: Vec<C>,
+    pub capacities: Option<Vec<usize>>,
+    pub constraints: Option<Vec<TubeConstraint>>,
+}
+
+impl<C: Cell> Tubes<C> {
+    pub fn into_game_state(self) -> GameState<C> {
+        let mut game = match self.capacities {
+            Some(capacities) => GameState::with_capacities(self.height, capacities, self.cells),
+            None => GameState::new(self.height, self.cells),
+        };
+        if let Some(constraints) = self.constraints {
+            game.set_constraints(constraints);
+        }
+        game
+    }
+}
+
+/// Reads one line of space-separated colors per tube from stdin, like [`read_tubes_with_capacities`],
+/// but rejects tube capacities and constraints, naming `command` as not supporting the latter.
+pub fn read_tubes<C: Cell>(
+    command: &str,
+    color_count: usize,
+    height: usize,
+    tube_count: usize,
+) -> Result<Vec<C>, InputError> {
+    let tubes = read_tubes_with_capacities(color_count, height, tube_count)?;
+    if tubes.capacities.is_some() {
+        return Err(InputError::UnsupportedCapacities);
+    }
+    if tubes.constraints.is_some() {
+        return Err(InputError::UnsupportedConstraints(command.into()));
+    }
+    Ok(tubes.cells)
+}
+
+/// A constraint token, with the color of `{only:COLOR}` still unnumbered.
+enum ConstraintToken {
+    Only(String),
+    Constraint(TubeConstraint),
+}
+
+fn parse_constraint(token: &str) -> Option<ConstraintToken> {
+    let constraint = token.strip_prefix('{')?.strip_suffix('}')?;
+    match constraint.split_once(':') {
+        Some(("only", color)) if !color.is_empty() => Some(ConstraintToken::Only(color.into())),
+        Some(("locked", completed)) => Some(ConstraintToken::Constraint(TubeConstraint::Locked {
+            completed: completed.parse().ok()?,
+        })),
+        None if constraint == "source" => {
+            Some(ConstraintToken::Constraint(TubeConstraint::SourceOnly))
+        }
+        None if constraint == "sink" => Some(ConstraintToken::Constraint(TubeConstraint::SinkOnly)),
+        _ => None,
+    }
+}
+
+/// Reads one line of space-separated colors per tube from stdin, bottom first, and numbers the
+/// colors from 1 in order of appearance.
+///
+/// A line may start with `[N]` to give its tube a capacity of `N` cells instead of `height`, then
+/// with one of these constraints:
+///
+/// - `{only:COLOR}`: the tube only accepts pours of `COLOR`,
+/// - `{locked:N}`: the tube takes part in no pour until `N` tubes are completed,
+/// - `{source}`: the tube can only be poured from,
+/// - `{sink}`: the tube can only be poured into.
+pub fn read_tubes_with_capacities<C: Cell>(
+    color_count: usize,
+    height: usize,
+    tube_count: usize,
+) -> Result<Tubes<C>, InputError> {
+    read_tubes_from(&mut stdin().lock(), color_count, height, tube_count)
+}
+
+/// Reads the tubes from `input`, see [`read_tubes_with_capacities`].
+fn read_tubes_from<C: Cell>(
+    input: &mut impl BufRead,
+    color_count: usize,
+    height: usize,
+    tube_count: usize,
+) -> Result<Tubes<C>, InputError> {
+    let mut color_list = vec![];
+    let mut capacities = vec![];
+    let mut constraint_tokens = vec![];
+    for _ in 0..tube_count {
+        let mut line_input = String::new();
+        input.read_line(&mut line_input)?;
+        let mut tokens = line_input.split_ascii_whitespace().peekable();
+        let capacity = match tokens.next_if(|token| token.starts_with('[')) {
+            Some(token) => token
+                .strip_prefix('[')
+                .and_then(|token| token.strip_suffix(']'))
+                .and_then(|capacity| capacity.parse::<usize>().ok())
+                .ok_or_else(|| InputError::InvalidCapacity(token.into()))?,
+            None => height,
+        };
+        capacities.push(capacity);
+        let constraint = match tokens.next_if(|token| token.starts_with('{')) {
+            Some(token) => Some(
+                parse_constraint(token)
+                    .ok_or_else(|| InputError::InvalidConstraint(token.into()))?,
+            ),
+            None => None,
+        };
+        constraint_tokens.push(constraint);
+        color_list.push(
+            tokens
+                .take(capacity)
+                .map(String::from)
+                .collect::<Vec<String>>(),
+        );
+    }
+    let mut color_map = FxHashMap::<String, (usize, usize)>::default();
+    for colors in color_list.iter() {
+        for c in colors {
+            if let Some(item) = color_map.get_mut(c) {
+                item.1 += 1;
+            } else {
+                color_map.insert(c.to_string(), (color_map.len(), 1));
+            }
+        }
+    }
+    if color_map.len() != color_count {
+        return Err(InputError::ColorCount {
+            expected: color_count,
+            actual: color_map.len(),
+        });
+    }
+    if color_count > C::MAX_COLOR {
+        return Err(InputError::TooManyColors {
+            colors: color_count,
+            max: C::MAX_COLOR,
+        });
+    }
+    for (color, &(_, count)) in color_map.iter() {
+        if count != height {
+            return Err(InputError::ColorCells {
+                color: color.clone(),
+                expected: height,
+                actual: count,
+            });
+        }
+    }
+    let stride = capacities.iter().copied().max().unwrap_or(height);
+    let mut tubes = vec![C::EMPTY; tube_count * stride];
+    for (tube_index, colors) in color_list.iter().enumerate() {
+        for (index, c) in colors.iter().enumerate() {
+            tubes[tube_index * stride + index] = C::from_color(color_map.get(c).unwrap().0 + 1);
+        }
+    }
+    let constraints = if constraint_tokens.iter().all(Option::is_none) {
+        None
+    } else {
+        let mut constraints = vec![];
+        for token in constraint_tokens {
+            constraints.push(match token {
+                Some(ConstraintToken::Only(color)) => match color_map.get(&color) {
+                    Some(&(number, _)) => TubeConstraint::SingleColor(number + 1),
+                    None => return Err(InputError::InvalidConstraint(format!("{{only:{color}}}"))),
+                },
+                Some(ConstraintToken::Constraint(constraint)) => constraint,
+                None => TubeConstraint::Free,
+            });
+        }
+        Some(constraints)
+    };
+    if capacities.iter().all(|&capacity| capacity == height) {
+        GameState::try_new(height, tubes.clone())?;
+        return Ok(Tubes {
+            height,
+            cells: tubes,
+            capacities: None,
+            constraints,
+        });
+    }
+    GameState::try_with_capacities(height, capacities.clone(), tubes.clone())?;
+    Ok(Tubes {
+        height,
+        cells: tubes,
+        capacities: Some(capacities),
+        constraints,
+    })
+}
+
+/// Tubes read from stdin with hidden cells, see [`read_hidden_tubes`].
+pub struct HiddenTubes<C: Cell> {
+    /// The cells, with `None` for hidden ones.
+    pub cells: Vec<Option<C>>,
+    /// The number given to each color name read.
+    pub color_map: FxHashMap<String, usize>,
+}
+
+/// Reads one line of space-separated colors per tube from stdin like [`read_tubes`], where `?` is
+/// a hidden cell.
+pub fn read_hidden_tubes<C: Cell>(
+    color_count: usize,
+    height: usize,
+    tube_count: usize,
+) -> Result<HiddenTubes<C>, InputError> {
+    if color_count >= C::MAX_COLOR {
+        return Err(InputError::TooManyColors {
+            colors: color_count,
+            max: C::MAX_COLOR - 1,
+        });
+    }
+    let mut color_map = FxHashMap::<String, usize>::default();
+    let mut counts = vec![0; color_count + 1];
+    let mut tubes = vec![None; tube_count * height];
+    for tube_index in 0..tube_count {
+        let mut line_input = String::new();
+        stdin().read_line(&mut line_input)?;
+        for (index, c) in line_input.split_ascii_whitespace().take(height).enumerate() {
+            if c == "?" {
+                tubes[tube_index * height + index] = Some(None);
+                continue;
+            }
+            let next_color = color_map.len() + 1;
+            let color = *color_map.entry(c.to_string()).or_insert(next_color);
+            if color > color_count {
+                return Err(InputError::ColorCount {
+                    expected: color_count,
+                    actual: color,
+                });
+            }
+            counts[color] += 1;
+            if counts[color] > height {
+                return Err(InputError::ColorCells {
+                    color: c.to_string(),
+                    expected: height,
+                    actual: counts[color],
+                });
+            }
+            tubes[tube_index * height + index] = Some(Some(C::from_color(color)));
+        }
+    }
+    let cells = tubes
+        .into_iter()
+        .map(|cell| cell.unwrap_or(Some(C::EMPTY)))
+        .collect();
+    Ok(HiddenTubes { cells, color_map })
+}
+
+/// Parses a move written as `a -> b` or `a b`, with tubes numbered from 1.
+pub fn parse_step(line: &str) -> Option<Move> {
+    let mut tubes = line
+        .split(|c: char| c.is_ascii_whitespace() || c == '-' || c == '>' || c == ',')
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse::<usize>().ok().filter(|&tube| tube > 0));
+    let from = tubes.next()??;
+    let to = tubes.next()??;
+    if tubes.next().is_some() {
+        return None;
+    }
+    Some(Move {
+        from: from - 1,
+        to: to - 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &str, colors: usize, height: usize) -> Result<Tubes<u8>, InputError> {
+        read_tubes_from(&mut input.as_bytes(), colors, height, input.lines().count())
+    }
+
+    #[test]
+    fn capacities() {
+        let tubes = read("[4] a b a\n[2] b b\n[3] a\n[1]\n", 2, 3).unwrap();
+        assert_eq!(tubes.capacities, Some(vec![4, 2, 3, 1]));
+        assert_eq!(tubes.constraints, None);
+        assert_eq!(
+            tubes.cells,
+            vec![1, 2, 1, 0, 2, 2, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn full_capacities_are_dropped() {
+        let tubes = read("[2] a b\nb a\n[2]\n", 2, 2).unwrap();
+        assert_eq!(tubes.capacities, None);
+        assert_eq!(tubes.cells, vec![1, 2, 2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn cells_beyond_capacity_are_ignored() {
+        let tubes = read("[1] a a\na b\nb\n", 2, 2).unwrap();
+        assert_eq!(tubes.capacities, Some(vec![1, 2, 2]));
+        assert_eq!(tubes.cells, vec![1, 0, 1, 2, 2, 0]);
+    }
+
+    #[test]
+    fn invalid_capacities() {
+        for line in ["[x] a b", "[2 a b", "[-1] a b", "[] a b"] {
+            let input = format!("{line}\nb a\n\n");
+            assert!(matches!(
+                read(&input, 2, 2),
+                Err(InputError::InvalidCapacity(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn constraints() {
+        let input = "{only:b} a b\n{locked:1} b a\n{source} a\n[3] {sink} b\n\n";
+        let tubes = read(input, 2, 3).unwrap();
+        assert_eq!(tubes.capacities, None);
+        assert_eq!(
+            tubes.constraints,
+            Some(vec![
+                TubeConstraint::SingleColor(2),
+                TubeConstraint::Locked { completed: 1 },
+                TubeConstraint::SourceOnly,
+                TubeConstraint::SinkOnly,
+                TubeConstraint::Free,
+            ])
+        );
+        assert_eq!(tubes.cells[..7], [1, 2, 0, 2, 1, 0, 1]);
+    }
+
+    #[test]
+    fn constraints_after_capacity() {
+        let tubes = read("[3] {sink} a b\nb a\n[1]\n", 2, 2).unwrap();
+        assert_eq!(tubes.capacities, Some(vec![3, 2, 1]));
+        assert_eq!(
+            tubes.constraints,
+            Some(vec![
+                TubeConstraint::SinkOnly,
+                TubeConstraint::Free,
+                TubeConstraint::Free,
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_constraints() {
+        for line in [
+            "{only:c} a b",
+            "{only:} a b",
+            "{locked:x} a b",
+            "{locked} a b",
+            "{faucet} a b",
+            "{sink a b",
+        ] {
+            let input = format!("{line}\nb a\n\n");
+            assert!(
+                matches!(read(&input, 2, 2), Err(InputError::InvalidConstraint(_))),
+                "{line}"
+            );
+        }
+    }
+}
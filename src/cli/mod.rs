@@ -1,5 +1,15 @@
 mod analyze;
+mod budget;
+mod hint;
+mod input;
+mod mystery;
+mod position;
 mod solve;
+mod verify;
 
 pub use analyze::{AnalyzerArgs, run_analyzer};
+pub use hint::{HintArgs, run_hint};
+pub use mystery::{MysteryArgs, run_mystery};
+pub use position::{PositionArgs, run_position_analyzer};
 pub use solve::{SolverArgs, run_solver};
+pub use verify::{VerifierArgs, run_verifier};
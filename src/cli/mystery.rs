@@ -0,0 +1,150 @@
+use std::io::{BufRead, stdin};
+
+use clap::Args;
+use rustc_hash::FxHashMap;
+
+use water_sort_puzzle_solver::*;
+
+use super::budget::BudgetArgs;
+use super::input::{HiddenTubes, InputError, parse_step, read_hidden_tubes};
+
+#[derive(Args)]
+pub struct MysteryArgs {
+    /// Number of colors
+    #[arg(short, long, value_parser)]
+    pub colors: usize,
+
+    /// Height of each tube
+    #[arg(short = 'H', long, value_parser)]
+    pub height: usize,
+
+    /// Number of tubes (default: colors + 2)
+    #[arg(short, long, value_parser)]
+    pub tubes: Option<usize>,
+
+    /// Number of hidden color arrangements each move is tried against
+    #[arg(long, value_parser, default_value_t = DEFAULT_MYSTERY_SAMPLES)]
+    pub samples: usize,
+
+    /// Seed used to pick arrangements when there are too many to try them all
+    #[arg(long, value_parser, default_value_t = 0)]
+    pub seed: u64,
+
+    #[command(flatten)]
+    pub budget: BudgetArgs,
+}
+
+/// Reads the tubes, with `?` for hidden cells, then suggests moves while reading the moves played
+/// as `a -> b` and the revealed colors as `tube = color`.
+pub fn run_mystery(subcommand: &MysteryArgs) -> Result<(), InputError> {
+    if subcommand.colors < u8::MAX_COLOR {
+        play::<u8>(subcommand)
+    } else {
+        play::<u16>(subcommand)
+    }
+}
+
+fn play<C: Cell>(subcommand: &MysteryArgs) -> Result<(), InputError> {
+    let color_count: usize = subcommand.colors;
+    let height: usize = subcommand.height;
+    let tube_count: usize = subcommand.tubes.unwrap_or(color_count + 2);
+    let HiddenTubes {
+        cells,
+        mut color_map,
+    } = read_hidden_tubes::<C>(color_count, height, tube_count)?;
+    let mut session = MysterySession::new(height, color_count, cells)?;
+    session.set_samples(subcommand.samples);
+    session.set_seed(subcommand.seed);
+    session.set_config(subcommand.budget.to_config());
+
+    suggest(&session, height);
+    for line in stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some((tube, color)) = line.split_once('=') {
+            let tube = tube
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&tube| tube > 0)
+                .ok_or_else(|| InputError::InvalidMove(line.into()))?;
+            let color = get_color(&mut color_map, color.trim(), color_count);
+            if let Err(error) = session.reveal(tube - 1, C::from_color(color)) {
+                println!("Cannot reveal {line}: {error}.");
+                continue;
+            }
+        } else {
+            let step = parse_step(line).ok_or_else(|| InputError::InvalidMove(line.into()))?;
+            if let Err(error) = session.play(step) {
+                println!("Cannot play {line}: {error}.");
+                continue;
+            }
+        }
+        suggest(&session, height);
+    }
+    Ok(())
+}
+
+/// Numbers a color in order of appearance, or returns an invalid number once every color is
+/// known.
+fn get_color(color_map: &mut FxHashMap<String, usize>, color: &str, color_count: usize) -> usize {
+    if let Some(&number) = color_map.get(color) {
+        return number;
+    }
+    if color_map.len() == color_count {
+        return color_count + 1;
+    }
+    let number = color_map.len() + 1;
+    color_map.insert(color.to_string(), number);
+    number
+}
+
+fn suggest<C: Cell>(session: &MysterySession<C>, height: usize) {
+    let cells = session.cells();
+    let hidden_tops: Vec<String> = cells
+        .chunks_exact(height)
+        .enumerate()
+        .filter(|(_, tube)| {
+            tube.iter()
+                .rev()
+                .find(|cell| *cell != &Some(C::EMPTY))
+                .is_some_and(Option::is_none)
+        })
+        .map(|(index, _)| (index + 1).to_string())
+        .collect();
+    if !hidden_tops.is_empty() {
+        println!("Reveal the top of tube {}.", hidden_tops.join(", "));
+        return;
+    }
+    if session.is_solved() {
+        println!("Solved.");
+        return;
+    }
+    match session.plan() {
+        Some(planned) if planned.is_safe() && planned.sampled => println!(
+            "{} -> {}: safe in {} sampled arrangements, at most {} moves",
+            planned.step.from + 1,
+            planned.step.to + 1,
+            planned.arrangements,
+            planned.worst_case.unwrap()
+        ),
+        Some(planned) if planned.is_safe() => println!(
+            "{} -> {}: safe, at most {} moves",
+            planned.step.from + 1,
+            planned.step.to + 1,
+            planned.worst_case.unwrap()
+        ),
+        Some(planned) => println!(
+            "{} -> {}: loses in {} of {} {}arrangements",
+            planned.step.from + 1,
+            planned.step.to + 1,
+            planned.losing,
+            planned.arrangements,
+            if planned.sampled { "sampled " } else { "" }
+        ),
+        None => println!("No move left."),
+    }
+}
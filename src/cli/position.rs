@@ -0,0 +1,73 @@
+use clap::Args;
+
+use water_sort_puzzle_solver::*;
+
+use super::budget::BudgetArgs;
+use super::input::{InputError, read_tubes};
+
+#[derive(Args)]
+pub struct PositionArgs {
+    /// Number of colors
+    #[arg(short, long, value_parser)]
+    pub colors: usize,
+
+    /// Height of each tube
+    #[arg(short = 'H', long, value_parser)]
+    pub height: usize,
+
+    /// Number of tubes (default: colors + 2)
+    #[arg(short, long, value_parser)]
+    pub tubes: Option<usize>,
+
+    #[command(flatten)]
+    pub budget: BudgetArgs,
+}
+
+fn print_outcome(outcome: SearchOutcome) {
+    match outcome {
+        SearchOutcome::BudgetExceeded { reason } => println!("search stopped: {reason}."),
+        _ => println!("search cancelled."),
+    }
+}
+
+pub fn run_position_analyzer(subcommand: &PositionArgs) -> Result<(), InputError> {
+    let color_count: usize = subcommand.colors;
+    let height: usize = subcommand.height;
+    let tube_count: usize = subcommand.tubes.unwrap_or(color_count + 2);
+    let config = subcommand.budget.to_config();
+    let analysis = if color_count <= u8::MAX_COLOR {
+        let tubes: Vec<u8> = read_tubes("analyze-position", color_count, height, tube_count)?;
+        analyze_position(height, tubes, config)
+    } else {
+        let tubes: Vec<u16> = read_tubes("analyze-position", color_count, height, tube_count)?;
+        analyze_position(height, tubes, config)
+    };
+
+    let analysis = match analysis {
+        Ok(analysis) => analysis,
+        Err(outcome) => {
+            print!("Position unknown, ");
+            print_outcome(outcome);
+            return Ok(());
+        }
+    };
+    match analysis.optimal_moves {
+        Some(moves) => println!("Optimal solution length: {moves}"),
+        None => println!("No solution."),
+    }
+    for analysis in analysis.moves {
+        print!("{} -> {}: ", analysis.step.from + 1, analysis.step.to + 1);
+        match (analysis.class, analysis.remaining) {
+            (MoveClass::Optimal, Some(remaining)) => println!("optimal, {remaining} moves left"),
+            (MoveClass::Suboptimal { extra }, Some(remaining)) => {
+                println!("suboptimal (+{extra}), {remaining} moves left")
+            }
+            (MoveClass::Unknown(outcome), _) => {
+                print!("unknown, ");
+                print_outcome(outcome);
+            }
+            _ => println!("losing"),
+        }
+    }
+    Ok(())
+}
@@ -1,17 +1,111 @@
-use std::io::stdin;
-use std::process;
 use std::time;
 
-use clap::Args;
-use rustc_hash::FxHashMap;
+use clap::{Args, ValueEnum};
+use itertools::Itertools;
 
 use water_sort_puzzle_solver::*;
 
-fn solve(solver: &mut impl Solver) {
+use super::budget::BudgetArgs;
+use super::input::{InputError, Tubes, read_tubes_with_capacities};
+
+fn print_stats(stats: &SearchStats) {
+    println!("Expanded states: {}", stats.expanded_states);
+    println!("Duplicate states: {}", stats.duplicate_states);
+    println!("Peak visited states: {}", stats.peak_visited_states);
+    if stats.max_stack_depth > 0 {
+        println!("Max stack depth: {}", stats.max_stack_depth);
+    }
+    if !stats.frontier_sizes.is_empty() {
+        println!(
+            "Frontier sizes: {}",
+            stats.frontier_sizes.iter().map(usize::to_string).join(", ")
+        );
+    }
+}
+
+fn solve<C: Cell>(
+    solver: &mut impl Solver<C>,
+    subcommand: &SolverArgs,
+    height: usize,
+    tubes: &[C],
+) {
+    solver.set_config(subcommand.budget.to_config());
+    let now = time::Instant::now();
+    match solver.search() {
+        SearchOutcome::Solved => {
+            let mut solution = solver.get_solution();
+            let length = solution.len();
+            if subcommand.polish {
+                solution = polish_solution(height, tubes, &solution, DEFAULT_POLISH_WINDOW);
+            }
+            for step in solution.iter() {
+                println!("{} -> {}", step.from + 1, step.to + 1);
+            }
+            if subcommand.polish {
+                println!("Solution length: {length}, polished: {}", solution.len());
+            }
+        }
+        SearchOutcome::Unsolvable => println!("No solution."),
+        SearchOutcome::BudgetExceeded { reason } => println!("Search stopped: {reason}."),
+        SearchOutcome::Cancelled => println!("Search cancelled."),
+        SearchOutcome::Exhausted => println!("No solution found."),
+    }
+    println!("Time used: {} seconds", now.elapsed().as_secs_f64());
+    if subcommand.stats {
+        print_stats(solver.get_stats());
+    }
+}
+
+/// Like [`solve`], printing where empty tubes are added to the solution.
+fn solve_with_extra_tubes<C: Cell>(
+    solver: &mut ExtraTubeSolver<C>,
+    subcommand: &SolverArgs,
+    tube_count: usize,
+) {
+    solver.set_config(subcommand.budget.to_config());
+    let now = time::Instant::now();
+    match solver.search() {
+        SearchOutcome::Solved => {
+            let extra_tubes = solver.get_extra_tubes();
+            let mut added = extra_tubes.iter().peekable();
+            let mut tubes = tube_count;
+            for (index, step) in solver.get_solution().iter().enumerate() {
+                while added.next_if(|&&pours| pours == index).is_some() {
+                    tubes += 1;
+                    println!("Add tube {tubes}");
+                }
+                println!("{} -> {}", step.from + 1, step.to + 1);
+            }
+            match extra_tubes.len() {
+                0 => println!("No extra tube needed."),
+                count => println!("Extra tubes used: {count}"),
+            }
+        }
+        SearchOutcome::Unsolvable => println!("No solution."),
+        SearchOutcome::BudgetExceeded { reason } => println!("Search stopped: {reason}."),
+        SearchOutcome::Cancelled => println!("Search cancelled."),
+        SearchOutcome::Exhausted => println!("No solution found."),
+    }
+    println!("Time used: {} seconds", now.elapsed().as_secs_f64());
+    if subcommand.stats {
+        print_stats(solver.get_stats());
+    }
+}
+
+fn count_solutions<C: Cell>(height: usize, tubes: Vec<C>, limit: usize) {
     let now = time::Instant::now();
-    if solver.search() {
-        for step in solver.get_solution() {
-            println!("{} -> {}", step.from + 1, step.to + 1);
+    if let Some(optimal) = count_optimal_solutions(height, tubes, limit) {
+        println!("Optimal solution length: {}", optimal.moves);
+        println!("Number of optimal solutions: {}", optimal.count);
+        for (index, solution) in optimal.solutions.iter().enumerate() {
+            println!(
+                "Solution {}: {}",
+                index + 1,
+                solution
+                    .iter()
+                    .map(|step| format!("{} -> {}", step.from + 1, step.to + 1))
+                    .join(", ")
+            );
         }
     } else {
         println!("No solution.");
@@ -19,6 +113,44 @@ fn solve(solver: &mut impl Solver) {
     println!("Time used: {} seconds", now.elapsed().as_secs_f64());
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    /// Breadth-first search, optimal
+    Bfs,
+    /// Depth-first search, suboptimal
+    Dfs,
+    /// A* search, optimal
+    #[value(name = "astar")]
+    AStar,
+    /// Iterative-deepening A* search, optimal with bounded memory
+    #[value(name = "idastar")]
+    IDAStar,
+    /// Bidirectional breadth-first search, optimal
+    Bidirectional,
+    /// Depth-first search in random move order with restarts, suboptimal
+    #[value(name = "restart-dfs")]
+    RestartDfs,
+    /// Depth-first search, then shorter solutions until optimal or out of budget
+    Anytime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Rules {
+    /// Pour as much of the top color as fits (water sort)
+    Pour,
+    /// Move a single unit (ball sort)
+    Unit,
+}
+
+impl From<Rules> for RuleSet {
+    fn from(rules: Rules) -> Self {
+        match rules {
+            Rules::Pour => RuleSet::PourRun,
+            Rules::Unit => RuleSet::SingleUnit,
+        }
+    }
+}
+
 #[derive(Args)]
 pub struct SolverArgs {
     /// Number of colors
@@ -34,68 +166,227 @@ pub struct SolverArgs {
     pub tubes: Option<usize>,
 
     /// Use suboptimal solver (DFS)
-    #[arg(long)]
+    #[arg(long, conflicts_with = "algorithm")]
     pub suboptimal: bool,
+
+    /// Search algorithm
+    #[arg(short, long, value_enum, default_value_t = Algorithm::Bfs)]
+    pub algorithm: Algorithm,
+
+    /// Game rules, where unit is only supported by bfs and dfs
+    #[arg(short, long, value_enum, default_value_t = Rules::Pour)]
+    pub rules: Rules,
+
+    /// Number of threads used by BFS (default: single-threaded)
+    #[arg(short = 'j', long, value_parser)]
+    pub threads: Option<usize>,
+
+    /// Use beam search keeping this many states per depth, suboptimal
+    #[arg(long, value_parser, conflicts_with_all = ["suboptimal", "algorithm", "threads"])]
+    pub beam: Option<usize>,
+
+    /// Seed of the move shuffling of restart-dfs
+    #[arg(long, value_parser, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Maximum number of states kept in the IDA* transposition table
+    #[arg(long, value_parser, default_value_t = DEFAULT_TABLE_CAPACITY)]
+    pub table_size: usize,
+
+    /// Count all optimal solutions, without a time or state limit
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "suboptimal", "algorithm", "threads", "beam", "timeout", "max_states", "stats"
+        ]
+    )]
+    pub all_optimal: bool,
+
+    /// Number of optimal solutions to list with --all-optimal
+    #[arg(long, value_parser, default_value_t = 0, requires = "all_optimal")]
+    pub list: usize,
+
+    /// Allow adding up to this many empty tubes, as few as possible and as late as possible
+    #[arg(
+        long,
+        value_parser,
+        conflicts_with_all = ["suboptimal", "algorithm", "threads", "beam", "all_optimal"]
+    )]
+    pub extra_tubes: Option<usize>,
+
+    /// Shorten the solution found before printing it
+    #[arg(long, conflicts_with_all = ["all_optimal", "extra_tubes"])]
+    pub polish: bool,
+
+    /// Print search statistics
+    #[arg(long)]
+    pub stats: bool,
+
+    #[command(flatten)]
+    pub budget: BudgetArgs,
 }
 
-pub fn run_solver(subcommand: &SolverArgs) {
+pub fn run_solver(subcommand: &SolverArgs) -> Result<(), InputError> {
     let color_count: usize = subcommand.colors;
     let height: usize = subcommand.height;
     let tube_count: usize = subcommand.tubes.unwrap_or(color_count + 2);
-    let use_dfs = subcommand.suboptimal;
-
-    let mut color_list = vec![];
-    for _ in 0..tube_count {
-        let mut line_input = String::new();
-        match stdin().read_line(&mut line_input) {
-            Ok(_) => {
-                color_list.push(
-                    line_input
-                        .split_ascii_whitespace()
-                        .take(height)
-                        .map(String::from)
-                        .collect::<Vec<String>>(),
-                );
-            }
-            Err(error) => {
-                eprintln!("Error: {error}");
-                process::exit(1);
-            }
-        }
+    let algorithm = if subcommand.suboptimal {
+        Algorithm::Dfs
+    } else {
+        subcommand.algorithm
+    };
+
+    if color_count <= u8::MAX_COLOR {
+        solve_puzzle::<u8>(subcommand, algorithm, height, tube_count)
+    } else {
+        solve_puzzle::<u16>(subcommand, algorithm, height, tube_count)
     }
-    let mut color_map = FxHashMap::<String, (usize, usize)>::default();
-    for colors in color_list.iter() {
-        for c in colors {
-            if let Some(item) = color_map.get_mut(c) {
-                item.1 += 1;
-            } else {
-                color_map.insert(c.to_string(), (color_map.len(), 1));
-            }
+}
+
+/// The first option given that does not support tube constraints.
+fn unconstrained_option(subcommand: &SolverArgs) -> Option<&'static str> {
+    [
+        (subcommand.threads.is_some(), "--threads"),
+        (subcommand.beam.is_some(), "--beam"),
+        (subcommand.all_optimal, "--all-optimal"),
+        (subcommand.extra_tubes.is_some(), "--extra-tubes"),
+        (subcommand.polish, "--polish"),
+    ]
+    .into_iter()
+    .find_map(|(given, name)| given.then_some(name))
+}
+
+fn solve_puzzle<C: Cell>(
+    subcommand: &SolverArgs,
+    algorithm: Algorithm,
+    height: usize,
+    tube_count: usize,
+) -> Result<(), InputError> {
+    let Tubes {
+        cells: tubes,
+        capacities,
+        constraints,
+        ..
+    } = read_tubes_with_capacities::<C>(subcommand.colors, height, tube_count)?;
+    if capacities.is_some()
+        && (!matches!(algorithm, Algorithm::Bfs | Algorithm::Dfs)
+            || subcommand.threads.is_some()
+            || subcommand.beam.is_some()
+            || subcommand.all_optimal
+            || subcommand.extra_tubes.is_some()
+            || subcommand.polish)
+    {
+        return Err(InputError::UnsupportedCapacities);
+    }
+    if constraints.is_some() {
+        let unsupported = if matches!(algorithm, Algorithm::Bfs | Algorithm::Dfs) {
+            unconstrained_option(subcommand).map(String::from)
+        } else {
+            algorithm
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+        };
+        if let Some(name) = unsupported {
+            return Err(InputError::UnsupportedConstraints(name));
         }
     }
-    if color_map.len() != color_count {
-        eprintln!(
-            "Number of colors mismatch: expected {color_count}, actual {}",
-            color_map.len()
-        );
-        process::exit(1);
+    let rules = RuleSet::from(subcommand.rules);
+    if rules != RuleSet::PourRun
+        && (!matches!(algorithm, Algorithm::Bfs | Algorithm::Dfs)
+            || subcommand.threads.is_some()
+            || subcommand.beam.is_some()
+            || subcommand.all_optimal
+            || subcommand.polish)
+    {
+        return Err(InputError::UnsupportedRules);
     }
-    for (color, &(_, count)) in color_map.iter() {
-        if count != height {
-            eprintln!("Color {color} count mismatch: expected {height}, actual {count}");
-            process::exit(1);
-        }
+
+    if let Some(width) = subcommand.beam {
+        let mut solver = BeamSolver::new(height, tubes.clone());
+        solver.set_width(width);
+        solve(&mut solver, subcommand, height, &tubes);
+        return Ok(());
     }
-    let mut tubes = vec![0; tube_count * height];
-    for (tube_index, colors) in color_list.iter().enumerate() {
-        for (index, c) in colors.iter().enumerate() {
-            tubes[tube_index * height + index] = (color_map.get(c).unwrap().0 + 1) as u8;
-        }
+
+    if subcommand.all_optimal {
+        count_solutions(height, tubes, subcommand.list);
+        return Ok(());
     }
 
-    if use_dfs {
-        solve(&mut DFSSolver::new(height, tubes));
-    } else {
-        solve(&mut BFSSolver::new(height, tubes));
+    if let Some(max_extra_tubes) = subcommand.extra_tubes {
+        let mut solver = ExtraTubeSolver::new(height, tubes.clone());
+        solver.set_max_extra_tubes(max_extra_tubes);
+        solver.set_rules(rules);
+        solve_with_extra_tubes(&mut solver, subcommand, tube_count);
+        return Ok(());
+    }
+
+    match algorithm {
+        Algorithm::Bfs => match subcommand.threads {
+            Some(threads) => {
+                let mut solver = ParallelBFSSolver::new(height, tubes.clone());
+                solver.set_threads(threads);
+                solve(&mut solver, subcommand, height, &tubes);
+            }
+            None => {
+                let mut solver = match capacities {
+                    Some(capacities) => {
+                        BFSSolver::with_capacities(height, capacities, tubes.clone())
+                    }
+                    None => BFSSolver::new(height, tubes.clone()),
+                };
+                solver.set_rules(rules);
+                if let Some(constraints) = constraints {
+                    solver.set_constraints(constraints);
+                }
+                solve(&mut solver, subcommand, height, &tubes);
+            }
+        },
+        Algorithm::Dfs => {
+            let mut solver = match capacities {
+                Some(capacities) => DFSSolver::with_capacities(height, capacities, tubes.clone()),
+                None => DFSSolver::new(height, tubes.clone()),
+            };
+            solver.set_rules(rules);
+            if let Some(constraints) = constraints {
+                solver.set_constraints(constraints);
+            }
+            solve(&mut solver, subcommand, height, &tubes);
+        }
+        Algorithm::RestartDfs => {
+            let mut solver = RestartDFSSolver::new(height, tubes.clone());
+            solver.set_seed(subcommand.seed);
+            solve(&mut solver, subcommand, height, &tubes);
+        }
+        Algorithm::AStar => {
+            let mut solver = AStarSolver::new(height, tubes.clone());
+            solve(&mut solver, subcommand, height, &tubes);
+        }
+        Algorithm::Bidirectional => {
+            let mut solver = BidirectionalSolver::new(height, tubes.clone());
+            solve(&mut solver, subcommand, height, &tubes);
+        }
+        Algorithm::IDAStar => {
+            let mut solver = IDAStarSolver::new(height, tubes.clone());
+            solver.set_table_capacity(subcommand.table_size);
+            solve(&mut solver, subcommand, height, &tubes);
+        }
+        Algorithm::Anytime => {
+            let mut solver = AnytimeSolver::new(height, tubes.clone());
+            solver.set_table_capacity(subcommand.table_size);
+            let now = time::Instant::now();
+            solver.set_callback(move |solution| {
+                println!(
+                    "Found a solution of {} moves after {} seconds",
+                    solution.len(),
+                    now.elapsed().as_secs_f64()
+                );
+            });
+            solve(&mut solver, subcommand, height, &tubes);
+            if !solver.get_solution().is_empty() && !solver.is_optimal() {
+                println!("Optimality not proven.");
+            }
+        }
     }
+    Ok(())
 }
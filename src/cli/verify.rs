@@ -0,0 +1,82 @@
+use std::io::{BufRead, stdin};
+use std::process;
+
+use clap::Args;
+
+use water_sort_puzzle_solver::*;
+
+use super::input::{InputError, parse_step, read_tubes_with_capacities};
+use super::solve::Rules;
+
+#[derive(Args)]
+pub struct VerifierArgs {
+    /// Number of colors
+    #[arg(short, long, value_parser)]
+    pub colors: usize,
+
+    /// Height of each tube
+    #[arg(short = 'H', long, value_parser)]
+    pub height: usize,
+
+    /// Number of tubes (default: colors + 2)
+    #[arg(short, long, value_parser)]
+    pub tubes: Option<usize>,
+
+    /// Game rules the moves are played with
+    #[arg(short, long, value_enum, default_value_t = Rules::Pour)]
+    pub rules: Rules,
+}
+
+pub fn run_verifier(subcommand: &VerifierArgs) -> Result<(), InputError> {
+    let color_count: usize = subcommand.colors;
+    let height: usize = subcommand.height;
+    let tube_count: usize = subcommand.tubes.unwrap_or(color_count + 2);
+    let rules = RuleSet::from(subcommand.rules);
+    if color_count <= u8::MAX_COLOR {
+        verify::<u8>(color_count, height, tube_count, rules)
+    } else {
+        verify::<u16>(color_count, height, tube_count, rules)
+    }
+}
+
+fn verify<C: Cell>(
+    color_count: usize,
+    height: usize,
+    tube_count: usize,
+    rules: RuleSet,
+) -> Result<(), InputError> {
+    let tubes = read_tubes_with_capacities::<C>(color_count, height, tube_count)?;
+
+    let mut steps = vec![];
+    for line in stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let step = parse_step(&line).ok_or_else(|| InputError::InvalidMove(line.trim().into()))?;
+        steps.push(step);
+    }
+
+    let mut state = tubes.into_game_state();
+    state.set_rules(rules);
+    match simulate_moves(state, &steps) {
+        Ok(simulation) if simulation.solved => {
+            println!("Valid solution in {} moves.", steps.len());
+            Ok(())
+        }
+        Ok(_) => {
+            println!("Puzzle not solved after {} moves.", steps.len());
+            process::exit(1);
+        }
+        Err(illegal) => {
+            println!(
+                "Illegal move {}: {} -> {}, {}.",
+                illegal.index + 1,
+                illegal.step.from + 1,
+                illegal.step.to + 1,
+                illegal.error
+            );
+            process::exit(1);
+        }
+    }
+}
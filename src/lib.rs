@@ -1,3 +1,13 @@
 mod solver;
 
-pub use crate::solver::{BFSSolver, DFSSolver, SolutionStep, Solver};
+pub use crate::solver::{
+    AStarSolver, AnytimeSolver, BFSSolver, BeamSolver, BeamWeights, BidirectionalSolver,
+    BudgetReason, CancellationToken, Cell, DEFAULT_BEAM_WIDTH, DEFAULT_MAX_EXTRA_TUBES,
+    DEFAULT_MAX_RESTARTS, DEFAULT_MYSTERY_SAMPLES, DEFAULT_POLISH_WINDOW, DEFAULT_RESTART_BASE,
+    DEFAULT_TABLE_CAPACITY, DFSSolver, ExtraTubeSolver, GameState, Hint, IDAStarSolver,
+    IllegalMove, Move, MoveAnalysis, MoveClass, MoveError, MysterySession, OptimalSolutions,
+    ParallelBFSSolver, PlannedMove, PositionAnalysis, PuzzleError, RestartDFSSolver, RevealError,
+    RuleSet, SearchConfig, SearchObserver, SearchOutcome, SearchStats, Simulation, SolutionStep,
+    Solver, TubeConstraint, analyze_position, count_optimal_solutions, get_hint,
+    get_solution_states, polish_solution, simulate, simulate_from, simulate_moves,
+};
@@ -1,3 +1,5 @@
+use std::process;
+
 use clap::{Parser, Subcommand};
 
 mod cli;
@@ -16,20 +18,47 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Solve the Water Sort Puzzle
+    ///
+    /// Reads one line of space-separated colors per tube from stdin, bottom first. A line may
+    /// start with `[N]` to give its tube a capacity of N cells, then with a constraint:
+    /// `{only:COLOR}` only accepts pours of COLOR, `{locked:N}` takes part in no pour until N tubes
+    /// are completed, `{source}` can only be poured from and `{sink}` only poured into.
+    ///
+    /// Capacities and constraints are only supported by single-threaded bfs and dfs, without
+    /// --beam, --all-optimal, --extra-tubes or --polish.
     Solve(SolverArgs),
 
     /// Analyze the Water Sort Puzzle
     Analyze(AnalyzerArgs),
+
+    /// Verify a solution of the Water Sort Puzzle
+    Verify(VerifierArgs),
+
+    /// Suggest the next move of the Water Sort Puzzle
+    Hint(HintArgs),
+
+    /// Classify every move of a Water Sort Puzzle position
+    AnalyzePosition(PositionArgs),
+
+    /// Suggest moves for a Water Sort Puzzle with hidden cells
+    Mystery(MysteryArgs),
 }
 
 fn main() {
     let cli = Cli::parse();
-    match &cli.command {
-        Commands::Solve(subcommand) => {
-            run_solver(subcommand);
-        }
+    let result = match &cli.command {
+        Commands::Solve(subcommand) => run_solver(subcommand),
         Commands::Analyze(subcommand) => {
             run_analyzer(subcommand);
+            Ok(())
         }
+        Commands::Verify(subcommand) => run_verifier(subcommand),
+        Commands::Hint(subcommand) => run_hint(subcommand),
+        Commands::AnalyzePosition(subcommand) => run_position_analyzer(subcommand),
+        Commands::Mystery(subcommand) => run_mystery(subcommand),
+    };
+    if let Err(error) = result {
+        eprintln!("Error: {error}");
+        process::exit(1);
     }
 }
@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use super::cell::Cell;
+use super::dfs_solver::DFSSolver;
+use super::idastar_solver::{DEFAULT_TABLE_CAPACITY, IDAStarSolver};
+use super::polish::{DEFAULT_POLISH_WINDOW, polish_solution};
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+type SolutionCallback = Box<dyn FnMut(&[SolutionStep])>;
+
+/// Forwards the progress of each phase to the observer of the whole search.
+struct SharedObserver(Rc<RefCell<Box<dyn SearchObserver>>>);
+
+impl SearchObserver for SharedObserver {
+    fn on_progress(&mut self, stats: &SearchStats) {
+        self.0.borrow_mut().on_progress(stats);
+    }
+}
+
+/// Finds a solution quickly with [`DFSSolver`], then looks for shorter ones with depth-bounded
+/// [`IDAStarSolver`] searches until none is left or the budget runs out.
+///
+/// Every improvement is passed to the callback set with [`AnytimeSolver::set_callback`]. The
+/// search is solved as soon as a first solution is known, and [`AnytimeSolver::is_optimal`]
+/// tells whether the last one was proven optimal. The time limit and the cancellation token
+/// cover the whole search, while state limits apply to each phase.
+pub struct AnytimeSolver<C: Cell = u8> {
+    height: usize,
+    initial_tubes: Vec<C>,
+    table_capacity: usize,
+    config: SearchConfig,
+    observer: Option<Rc<RefCell<Box<dyn SearchObserver>>>>,
+    callback: Option<SolutionCallback>,
+    solution: Vec<SolutionStep>,
+    optimal: bool,
+    stats: SearchStats,
+}
+
+impl<C: Cell> Solver<C> for AnytimeSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            initial_tubes,
+            table_capacity: DEFAULT_TABLE_CAPACITY,
+            config: SearchConfig::default(),
+            observer: None,
+            callback: None,
+            solution: vec![],
+            optimal: false,
+            stats: SearchStats::default(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.observer = Some(Rc::new(RefCell::new(observer)));
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.stats = SearchStats::default();
+        self.solution.clear();
+        self.optimal = false;
+        let deadline = self
+            .config
+            .max_duration
+            .map(|duration| Instant::now() + duration);
+
+        let mut solver = DFSSolver::new(self.height, self.initial_tubes.clone());
+        let outcome = self.run_phase(&mut solver, deadline, Solver::search);
+        if !outcome.is_solved() {
+            return outcome;
+        }
+        let solution = solver.get_solution();
+        let polished = polish_solution(
+            self.height,
+            &self.initial_tubes,
+            &solution,
+            DEFAULT_POLISH_WINDOW,
+        );
+        self.improve(polished);
+
+        let tube_count = self.initial_tubes.len() / self.height;
+        let (_, sorted_tubes) = get_transform(&self.initial_tubes, self.height, tube_count);
+        let color_tubes = get_color_tubes(&self.initial_tubes, self.height);
+        let lower_bound = get_lower_bound(&sorted_tubes, self.height, &color_tubes);
+        while self.solution.len() > lower_bound {
+            let mut solver = IDAStarSolver::new(self.height, self.initial_tubes.clone());
+            solver.set_table_capacity(self.table_capacity);
+            let max_moves = self.solution.len() - 1;
+            match self.run_phase(&mut solver, deadline, |solver| {
+                solver.search_within(max_moves)
+            }) {
+                SearchOutcome::Solved => self.improve(solver.get_solution()),
+                SearchOutcome::Unsolvable => break,
+                _ => return SearchOutcome::Solved,
+            }
+        }
+        self.optimal = true;
+        SearchOutcome::Solved
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        self.solution.clone()
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        &self.stats
+    }
+}
+
+impl<C: Cell> AnytimeSolver<C> {
+    /// Sets a function called with each solution shorter than the previous ones.
+    pub fn set_callback(&mut self, callback: impl FnMut(&[SolutionStep]) + 'static) {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// Limits the transposition table of the depth-bounded searches, see
+    /// [`IDAStarSolver::set_table_capacity`].
+    pub fn set_table_capacity(&mut self, capacity: usize) {
+        self.table_capacity = capacity;
+    }
+
+    /// Whether the last search proved that its solution is the shortest.
+    pub fn is_optimal(&self) -> bool {
+        self.optimal
+    }
+
+    fn run_phase<S: Solver<C>>(
+        &mut self,
+        solver: &mut S,
+        deadline: Option<Instant>,
+        search: impl FnOnce(&mut S) -> SearchOutcome,
+    ) -> SearchOutcome {
+        let mut config = self.config.clone();
+        config.max_duration =
+            deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        solver.set_config(config);
+        if let Some(observer) = &self.observer {
+            solver.set_observer(Box::new(SharedObserver(observer.clone())));
+        }
+        let outcome = search(solver);
+        self.stats.accumulate(solver.get_stats());
+        outcome
+    }
+
+    fn improve(&mut self, solution: Vec<SolutionStep>) {
+        self.solution = solution;
+        if let Some(callback) = self.callback.as_mut() {
+            callback(&self.solution);
+        }
+    }
+}
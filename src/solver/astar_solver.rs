@@ -0,0 +1,156 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use super::cell::Cell;
+use super::rules::RuleSet;
+use super::search::SearchControl;
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+struct Node<C> {
+    tubes: Rc<Vec<C>>,
+    parent: usize,
+    depth: usize,
+    from: usize,
+    to: usize,
+    transform: Vec<usize>,
+    closed: bool,
+}
+
+pub struct AStarSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    color_tubes: Vec<usize>,
+    nodes: Vec<Node<C>>,
+    states: FxHashMap<Rc<Vec<C>>, usize>,
+    queue: BinaryHeap<Reverse<(usize, Reverse<usize>, usize)>>,
+    goal: Option<usize>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for AStarSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            color_tubes: get_color_tubes(&initial_tubes, height),
+            initial_tubes,
+            nodes: vec![],
+            states: FxHashMap::default(),
+            queue: BinaryHeap::new(),
+            goal: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        self.push_state(
+            &self.initial_tubes.clone(),
+            usize::MAX,
+            0,
+            usize::MAX,
+            usize::MAX,
+        );
+        while let Some(Reverse((_, Reverse(depth), index))) = self.queue.pop() {
+            let node = &mut self.nodes[index];
+            if node.closed || node.depth != depth {
+                continue;
+            }
+            node.closed = true;
+            let tubes = node.tubes.clone();
+            if is_solved(&tubes, self.height) {
+                self.goal = Some(index);
+                return SearchOutcome::Solved;
+            }
+            if let Err(outcome) = self.control.expand(1, self.states.len()) {
+                return outcome;
+            }
+            let tube_stats: Vec<TubeStats> = tubes
+                .chunks_exact(self.height)
+                .map(|tube| get_tube_stat(tube, self.height))
+                .collect();
+            for (from, to, amount) in get_legal_moves(&tube_stats, self.height) {
+                let mut new_tubes = (*tubes).clone();
+                pour(&mut new_tubes, self.height, &tube_stats, from, to, amount);
+                self.push_state(&new_tubes, index, depth + 1, from, to);
+            }
+        }
+        SearchOutcome::Unsolvable
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let mut steps = vec![];
+        let mut index = self.goal.unwrap();
+        loop {
+            let node = &self.nodes[index];
+            steps.push(node);
+            if node.depth == 0 {
+                break;
+            }
+            index = node.parent;
+        }
+        steps.reverse();
+
+        let moves = get_moves_from_transforms(
+            self.tubes,
+            steps
+                .iter()
+                .map(|node| (node.from, node.to, node.transform.as_slice())),
+        );
+        replay_moves(&self.initial_tubes, self.height, &moves, RuleSet::PourRun)
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> AStarSolver<C> {
+    fn push_state(&mut self, tubes: &[C], parent: usize, depth: usize, from: usize, to: usize) {
+        let (transform, sorted_tubes) = get_transform(tubes, self.height, self.tubes);
+        let index = if let Some(&index) = self.states.get(&sorted_tubes) {
+            let node = &mut self.nodes[index];
+            if node.closed || node.depth <= depth {
+                self.control.record_duplicates(1);
+                return;
+            }
+            node.parent = parent;
+            node.depth = depth;
+            node.from = from;
+            node.to = to;
+            node.transform = transform;
+            index
+        } else {
+            let index = self.nodes.len();
+            let sorted_tubes = Rc::new(sorted_tubes);
+            self.nodes.push(Node {
+                tubes: sorted_tubes.clone(),
+                parent,
+                depth,
+                from,
+                to,
+                transform,
+                closed: false,
+            });
+            self.states.insert(sorted_tubes, index);
+            index
+        };
+        let tubes = &self.nodes[index].tubes;
+        let estimate = get_lower_bound(tubes, self.height, &self.color_tubes);
+        self.queue
+            .push(Reverse((depth + estimate, Reverse(depth), index)));
+    }
+}
@@ -0,0 +1,257 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::cell::Cell;
+use super::game_state::GameState;
+use super::packed_state::{PackedState, StatePacker};
+use super::rules::RuleSet;
+use super::search::SearchControl;
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+pub const DEFAULT_BEAM_WIDTH: usize = 1000;
+
+/// Weights of the terms scoring a state in [`BeamSolver`], where a lower score is better.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BeamWeights {
+    /// Penalty per pour needed at least to gather the color segments, which measures how
+    /// fragmented the colors are.
+    pub fragmentation: i64,
+    /// Reward per completed tube.
+    pub sorted_tubes: i64,
+    /// Reward per empty tube.
+    pub free_space: i64,
+}
+
+impl Default for BeamWeights {
+    fn default() -> Self {
+        Self {
+            fragmentation: 4,
+            sorted_tubes: 1,
+            free_space: 2,
+        }
+    }
+}
+
+/// A kept state, as the pour from its parent given in the parent's sorted tube order.
+struct State {
+    parent: u32,
+    from: u16,
+    to: u16,
+    amount: u16,
+}
+
+/// Breadth-first search keeping only the best scored states of each depth.
+///
+/// This scales to puzzles too large for the other solvers, but the solutions are not optimal and
+/// [`SearchOutcome::Exhausted`] is returned when the beam loses every path to a solution.
+pub struct BeamSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    color_tubes: Vec<usize>,
+    width: usize,
+    weights: BeamWeights,
+    packer: StatePacker,
+    states: FxHashSet<PackedState>,
+    nodes: Vec<State>,
+    goal: Option<u32>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for BeamSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            packer: StatePacker::new(&initial_tubes),
+            color_tubes: get_color_tubes(&initial_tubes, height),
+            initial_tubes,
+            width: DEFAULT_BEAM_WIDTH,
+            weights: BeamWeights::default(),
+            states: FxHashSet::default(),
+            nodes: vec![],
+            goal: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        let (_, sorted_tubes) = get_transform(&self.initial_tubes, self.height, self.tubes);
+        self.nodes.push(State {
+            parent: u32::MAX,
+            from: 0,
+            to: 0,
+            amount: 0,
+        });
+        if is_solved(&sorted_tubes, self.height) {
+            self.goal = Some(0);
+            return SearchOutcome::Solved;
+        }
+        self.states.insert(self.packer.pack(&sorted_tubes));
+        self.control.record_frontier(0, 1);
+        let mut layer = vec![(0, sorted_tubes)];
+        let mut depth = 0;
+        while !layer.is_empty() {
+            if let Err(outcome) = self.control.expand(layer.len(), self.states.len()) {
+                return outcome;
+            }
+            // Children keyed by sorted state, keeping the first parent and pour found.
+            let mut children: FxHashMap<Vec<C>, State> = FxHashMap::default();
+            let mut generated = 0;
+            for (index, state) in layer.iter() {
+                let tube_stats: Vec<TubeStats> = state
+                    .chunks_exact(self.height)
+                    .map(|tube| get_tube_stat(tube, self.height))
+                    .collect();
+                for (from, to, amount) in get_bfs_moves(&tube_stats, self.height, RuleSet::PourRun)
+                {
+                    generated += 1;
+                    let mut tubes = state.clone();
+                    pour(&mut tubes, self.height, &tube_stats, from, to, amount);
+                    let (_, sorted_tubes) = get_transform(&tubes, self.height, self.tubes);
+                    if self.states.contains(&self.packer.pack(&sorted_tubes)) {
+                        continue;
+                    }
+                    children.entry(sorted_tubes).or_insert(State {
+                        parent: *index,
+                        from: from as u16,
+                        to: to as u16,
+                        amount: amount as u16,
+                    });
+                }
+            }
+            self.control.record_duplicates(generated - children.len());
+
+            let mut children: Vec<(i64, Vec<C>, State)> = children
+                .into_iter()
+                .map(|(tubes, pour)| (self.get_score(&tubes), tubes, pour))
+                .collect();
+            if children.len() > self.width {
+                children.select_nth_unstable_by(self.width, |x, y| (x.0, &x.1).cmp(&(y.0, &y.1)));
+                children.truncate(self.width);
+            }
+            children.sort_unstable_by(|x, y| (x.0, &x.1).cmp(&(y.0, &y.1)));
+
+            depth += 1;
+            self.control.record_frontier(depth, children.len());
+            layer = Vec::with_capacity(children.len());
+            for (_, tubes, state) in children {
+                let index = self.nodes.len() as u32;
+                self.nodes.push(state);
+                if is_solved(&tubes, self.height) {
+                    self.goal = Some(index);
+                    return SearchOutcome::Solved;
+                }
+                self.states.insert(self.packer.pack(&tubes));
+                layer.push((index, tubes));
+            }
+        }
+        SearchOutcome::Exhausted
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let mut moves = vec![];
+        let mut index = self.goal.unwrap();
+        while index > 0 {
+            let state = &self.nodes[index as usize];
+            moves.push((
+                state.from as usize,
+                state.to as usize,
+                state.amount as usize,
+            ));
+            index = state.parent;
+        }
+        moves.reverse();
+        get_steps_from_moves(
+            GameState::new(self.height, self.initial_tubes.clone()),
+            &moves,
+        )
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> BeamSolver<C> {
+    /// Sets the number of states kept per depth, defaulting to [`DEFAULT_BEAM_WIDTH`].
+    pub fn set_width(&mut self, width: usize) {
+        self.width = width.max(1);
+    }
+
+    pub fn set_weights(&mut self, weights: BeamWeights) {
+        self.weights = weights;
+    }
+
+    fn get_score(&self, state: &[C]) -> i64 {
+        let fragmentation = get_lower_bound(state, self.height, &self.color_tubes) as i64;
+        let mut sorted_tubes = 0;
+        let mut empty_tubes = 0;
+        for tube in state.chunks_exact(self.height) {
+            if tube[0] == C::EMPTY {
+                empty_tubes += 1;
+            } else if get_tube_stat(tube, self.height).color_height == self.height {
+                sorted_tubes += 1;
+            }
+        }
+        self.weights.fragmentation * fragmentation
+            - self.weights.sorted_tubes * sorted_tubes
+            - self.weights.free_space * empty_tubes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::bfs_solver::BFSSolver;
+    use crate::solver::game_state::GameState;
+
+    fn tubes() -> Vec<u8> {
+        parse_tubes(
+            &[
+                "h b d b", "h a a e", "f c b c", "e g e c", "g g a d", "g d b f", "d f f h",
+                "c a h e", "", "",
+            ],
+            4,
+        )
+    }
+
+    /// Searches with a beam of `width` states, checking that any solution found plays out.
+    fn solve(width: usize) -> Option<usize> {
+        let mut solver = BeamSolver::new(4, tubes());
+        solver.set_width(width);
+        match solver.search() {
+            SearchOutcome::Solved => {}
+            SearchOutcome::Exhausted => return None,
+            outcome => panic!("unexpected outcome {outcome:?}"),
+        }
+        let steps = solver.get_solution();
+        let mut game = GameState::new(4, tubes());
+        for &step in steps.iter() {
+            game.apply_step(step).unwrap();
+        }
+        assert!(game.is_solved());
+        Some(steps.len())
+    }
+
+    #[test]
+    fn narrow_beam() {
+        let mut solver = BFSSolver::new(4, tubes());
+        assert!(solver.search().is_solved());
+        let optimal = solver.get_solution().len();
+        assert_eq!(solve(1), None);
+        for width in [2, 3, 8] {
+            assert!(solve(width).unwrap() >= optimal);
+        }
+        assert_eq!(solve(DEFAULT_BEAM_WIDTH), Some(optimal));
+    }
+}
@@ -1,223 +1,240 @@
 use std::collections::VecDeque;
-use std::iter;
-use std::rc::Rc;
+use std::hash::BuildHasher;
 
-use rustc_hash::FxHashMap;
+use hashbrown::HashTable;
+use rustc_hash::FxBuildHasher;
 
+use super::cell::Cell;
+use super::constraint::TubeConstraint;
+use super::game_state::GameState;
+use super::packed_state::{PackedState, StatePacker};
+use super::rules::RuleSet;
+use super::search::SearchControl;
 use super::utils::*;
-use super::{SolutionStep, Solver};
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
 
+/// A visited state, as the pour from its parent given in the parent's sorted tube order.
 #[derive(Clone)]
 struct State {
-    tubes: Rc<Vec<u8>>,
-    depth: usize,
-    from: usize,
-    to: usize,
-    amount: usize,
-    transform: Vec<usize>,
+    parent: u32,
+    from: u16,
+    to: u16,
+    amount: u16,
 }
 
-pub struct BFSSolver {
+pub struct BFSSolver<C: Cell = u8> {
     height: usize,
+    stride: usize,
     tubes: usize,
-    initial_tubes: Vec<u8>,
-    states: FxHashMap<Rc<Vec<u8>>, Rc<State>>,
-    queue: VecDeque<Rc<State>>,
+    initial_tubes: Vec<C>,
+    initial_capacities: Vec<usize>,
+    initial_constraints: Vec<TubeConstraint>,
+    /// The capacities and constraints in sorted tube order, which is the same for every sorted
+    /// state.
+    capacities: Vec<usize>,
+    constraints: Vec<TubeConstraint>,
+    rules: RuleSet,
+    packer: StatePacker,
+    /// The sorted visited states, by node index, each stored only once.
+    packed_states: Vec<PackedState>,
+    /// Node indexes, hashed by their state, to find the visited states.
+    states: HashTable<u32>,
+    nodes: Vec<State>,
+    queue: VecDeque<(u32, usize)>,
+    goal: Option<u32>,
+    control: SearchControl,
 }
 
-impl Solver for BFSSolver {
-    fn new(height: usize, initial_tubes: Vec<u8>) -> Self {
-        Self {
-            height,
-            tubes: initial_tubes.len() / height,
-            initial_tubes,
-            states: FxHashMap::default(),
-            queue: VecDeque::new(),
-        }
+impl<C: Cell> Solver<C> for BFSSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        let tubes = initial_tubes.len() / height;
+        Self::with_capacities(height, vec![height; tubes], initial_tubes)
     }
 
-    fn search(&mut self) -> bool {
-        if self.push_state(&self.initial_tubes.clone(), 0, usize::MAX, usize::MAX, 0) {
-            return true;
-        }
-        while let Some(state) = self.queue.pop_front() {
-            if self.inner_search(state.as_ref()) {
-                return true;
-            }
-        }
-        false
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
     }
 
-    fn get_solution(&self) -> Vec<SolutionStep> {
-        let colors = self.initial_tubes.iter().filter(|&&x| x > 0).count() / self.height;
-        let mut state = vec![0; (self.tubes - colors) * self.height];
-        for i in 1..=colors {
-            state.extend(iter::repeat(i as u8).take(self.height));
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        let (_, sorted_tubes) = get_capacity_transform(
+            &self.initial_tubes,
+            self.stride,
+            &self.initial_capacities,
+            &self.initial_constraints,
+        );
+        if self.push_state(&sorted_tubes, u32::MAX, 0, 0, 0, 0) {
+            return SearchOutcome::Solved;
         }
-        let mut steps = vec![];
-        let mut inverse_transform: Vec<usize> = vec![0; self.tubes];
-        loop {
-            let step = self.states.get(&state).unwrap();
-            steps.push(step);
-            if step.depth == 0 {
-                break;
+        let mut tubes = vec![C::EMPTY; self.tubes * self.stride];
+        while let Some((index, depth)) = self.queue.pop_front() {
+            if let Err(outcome) = self.control.expand(1, self.states.len()) {
+                return outcome;
             }
-            for (i, &x) in step.transform.iter().enumerate() {
-                inverse_transform[x] = i;
+            self.packer
+                .unpack(&self.packed_states[index as usize], &mut tubes);
+            if self.inner_search(&tubes, index, depth) {
+                return SearchOutcome::Solved;
             }
-            let mut new_state = vec![0; self.tubes * self.height];
-            for i in 0..self.tubes {
-                for j in 0..self.height {
-                    new_state[i * self.height + j] = state[inverse_transform[i] * self.height + j];
-                }
-            }
-            state = new_state;
-            pour_back(&mut state, self.height, step.from, step.to, step.amount);
         }
-        steps.reverse();
-
-        let mut solution = vec![];
-        let mut transform: Vec<usize> = (0..self.tubes).collect();
-        let mut new_transform = vec![0; self.tubes];
-        for (index, step) in steps.iter().enumerate() {
-            if index > 0 {
-                solution.push(SolutionStep {
-                    from: transform[step.from],
-                    to: transform[step.to],
-                });
-            }
-            for i in 0..self.tubes {
-                new_transform[i] = transform[step.transform[i]];
-            }
-            std::mem::swap(&mut transform, &mut new_transform);
+        SearchOutcome::Unsolvable
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let mut moves = vec![];
+        let mut index = self.goal.unwrap();
+        while index > 0 {
+            let state = &self.nodes[index as usize];
+            moves.push((
+                state.from as usize,
+                state.to as usize,
+                state.amount as usize,
+            ));
+            index = state.parent;
         }
-        solution
+        moves.reverse();
+        let mut initial = GameState::with_capacities(
+            self.height,
+            self.initial_capacities.clone(),
+            self.initial_tubes.clone(),
+        );
+        initial.set_rules(self.rules);
+        initial.set_constraints(self.initial_constraints.clone());
+        get_steps_from_moves(initial, &moves)
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
     }
 }
 
-impl BFSSolver {
+impl<C: Cell> BFSSolver<C> {
+    /// Creates a solver for tubes of individual capacities, laid out as in
+    /// [`GameState::with_capacities`].
+    pub fn with_capacities(height: usize, capacities: Vec<usize>, initial_tubes: Vec<C>) -> Self {
+        let mut sorted_capacities = capacities.clone();
+        sorted_capacities.sort_unstable();
+        let constraints = vec![TubeConstraint::Free; capacities.len()];
+        Self {
+            height,
+            stride: capacities.iter().copied().max().unwrap_or(height),
+            tubes: capacities.len(),
+            packer: StatePacker::new(&initial_tubes),
+            initial_tubes,
+            initial_capacities: capacities,
+            initial_constraints: constraints.clone(),
+            capacities: sorted_capacities,
+            constraints,
+            rules: RuleSet::default(),
+            packed_states: vec![],
+            states: HashTable::new(),
+            nodes: vec![],
+            queue: VecDeque::new(),
+            goal: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    /// Restricts the pours each tube takes part in, one constraint per tube, see
+    /// [`GameState::set_constraints`].
+    pub fn set_constraints(&mut self, constraints: Vec<TubeConstraint>) {
+        assert_eq!(constraints.len(), self.tubes);
+        (self.capacities, self.constraints) =
+            sort_tube_kinds(&self.initial_capacities, &constraints);
+        self.initial_constraints = constraints;
+    }
+
     fn push_state(
         &mut self,
-        tubes: &[u8],
+        tubes: &[C],
+        parent: u32,
         depth: usize,
         from: usize,
         to: usize,
         amount: usize,
     ) -> bool {
-        let (transform, sorted_tubes) = get_transform(tubes, self.height, self.tubes);
-        if self.states.contains_key(&sorted_tubes) {
+        let (_, sorted_tubes) =
+            get_capacity_transform(tubes, self.stride, &self.capacities, &self.constraints);
+        let packed_tubes = self.packer.pack(&sorted_tubes);
+        let hash = FxBuildHasher.hash_one(&packed_tubes);
+        let packed_states = &self.packed_states;
+        if self
+            .states
+            .find(hash, |&index| packed_states[index as usize] == packed_tubes)
+            .is_some()
+        {
+            self.control.record_duplicates(1);
             return false;
         }
-        let solved = is_solved(&sorted_tubes, self.height);
-        let sorted_tubes = Rc::new(sorted_tubes);
-        let state = Rc::new(State {
-            tubes: sorted_tubes.clone(),
-            depth,
-            from,
-            to,
-            amount,
-            transform,
+        self.control.record_frontier(depth, 1);
+        let index = self.nodes.len() as u32;
+        self.nodes.push(State {
+            parent,
+            from: from as u16,
+            to: to as u16,
+            amount: amount as u16,
+        });
+        self.states.insert_unique(hash, index, |&index| {
+            FxBuildHasher.hash_one(&packed_states[index as usize])
         });
-        self.queue.push_back(state.clone());
-        self.states.insert(sorted_tubes, state);
-        solved
+        self.packed_states.push(packed_tubes);
+        self.queue.push_back((index, depth));
+        if is_goal(&sorted_tubes, self.stride, self.height) {
+            self.goal = Some(index);
+            return true;
+        }
+        false
     }
 
-    fn inner_search(&mut self, state: &State) -> bool {
-        let tube_stats: Vec<TubeStats> = state
-            .tubes
-            .chunks_exact(self.height)
-            .map(|tube| get_tube_stat(tube, self.height))
-            .collect();
-        for i in 0..(self.tubes - 1) {
-            if !tube_stats[i].simple || tube_stats[i].color_height == self.height {
-                continue;
-            }
-            for j in (i + 1)..self.tubes {
-                if tube_stats[j].simple
-                    && tube_stats[i].color == tube_stats[j].color
-                    && tube_stats[i].color_height + tube_stats[j].color_height >= self.height - 1
-                {
-                    let mut tubes = (*state.tubes).clone();
-                    pour(
-                        &mut tubes,
-                        self.height,
-                        &tube_stats,
-                        i,
-                        j,
-                        tube_stats[i].size,
-                    );
-                    return self.push_state(
-                        &tubes,
-                        state.depth + 1,
-                        i,
-                        j,
-                        tube_stats[i].color_height,
-                    );
-                }
-            }
-        }
-        for i in 0..self.tubes {
-            if !tube_stats[i].simple || tube_stats[i].color_height == self.height {
-                continue;
-            }
-            for j in 0..self.tubes {
-                if j == i || tube_stats[j].simple || tube_stats[i].color != tube_stats[j].color {
-                    continue;
-                }
-                let amount = tube_stats[j].color_height;
-                if tube_stats[i].color_height + amount == self.height {
-                    let mut tubes = (*state.tubes).clone();
-                    pour(&mut tubes, self.height, &tube_stats, j, i, amount);
-                    return self.push_state(&tubes, state.depth + 1, j, i, amount);
-                }
-            }
-        }
-        for i in 0..(self.tubes - 1) {
-            if tube_stats[i].color_height == self.height {
-                continue;
-            } else if tube_stats[i].color_height == 0 {
-                if i > 0 {
-                    continue;
-                }
-                for j in (i + 1)..self.tubes {
-                    if tube_stats[j].simple || tube_stats[j].color_height == 0 {
-                        continue;
-                    }
-                    let mut tubes = (*state.tubes).clone();
-                    let amount = tube_stats[j].color_height;
-                    pour(&mut tubes, self.height, &tube_stats, j, i, amount);
-                    if self.push_state(&tubes, state.depth + 1, j, i, amount) {
-                        return true;
-                    }
-                }
-            } else {
-                for j in (i + 1)..self.tubes {
-                    if tube_stats[j].color_height < self.height
-                        && tube_stats[i].color == tube_stats[j].color
-                    {
-                        let mut indexes = vec![];
-                        if tube_stats[j].size < self.height {
-                            indexes.push((i, j));
-                        }
-                        if tube_stats[i].size < self.height {
-                            indexes.push((j, i));
-                        }
-                        for (x, y) in indexes {
-                            let mut tubes = (*state.tubes).clone();
-                            let amount = usize::min(
-                                tube_stats[x].color_height,
-                                self.height - tube_stats[y].size,
-                            );
-                            pour(&mut tubes, self.height, &tube_stats, x, y, amount);
-                            if self.push_state(&tubes, state.depth + 1, x, y, amount) {
-                                return true;
-                            }
-                        }
-                    }
-                }
+    fn inner_search(&mut self, state: &[C], index: u32, depth: usize) -> bool {
+        let tube_stats =
+            get_capacity_stats(state, self.stride, &self.capacities, &self.constraints);
+        for (from, to, amount) in get_bfs_moves(&tube_stats, self.height, self.rules) {
+            let mut tubes = state.to_vec();
+            pour(&mut tubes, self.stride, &tube_stats, from, to, amount);
+            if self.push_state(&tubes, index, depth + 1, from, to, amount) {
+                return true;
             }
         }
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::utils::parse_tubes;
+
+    fn solve_with_capacities(height: usize, capacities: Vec<usize>, tubes: &[&str]) -> usize {
+        let tubes: Vec<u8> = parse_tubes(tubes, 5);
+        let mut solver = BFSSolver::with_capacities(height, capacities.clone(), tubes.clone());
+        assert!(solver.search().is_solved());
+        let steps = solver.get_solution();
+        let mut state = GameState::with_capacities(height, capacities, tubes);
+        for &step in &steps {
+            state.apply_step(step).unwrap();
+        }
+        assert!(state.is_solved());
+        steps.len()
+    }
+
+    #[test]
+    fn no_forced_merge_into_larger_tube() {
+        let tubes = ["b", "c c", "b a a a", "b c"];
+        assert_eq!(solve_with_capacities(3, vec![5, 2, 5, 5], &tubes), 6);
+    }
+
+    #[test]
+    fn no_forced_merge_into_smaller_tube() {
+        let tubes = ["b c a a", "b a b a c", "c b c", ""];
+        solve_with_capacities(4, vec![4, 5, 3, 4], &tubes);
+    }
+}
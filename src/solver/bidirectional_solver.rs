@@ -0,0 +1,174 @@
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use super::cell::Cell;
+use super::search::SearchControl;
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+struct Node<C> {
+    tubes: Rc<Vec<C>>,
+    parent: usize,
+    depth: usize,
+}
+
+#[derive(Default)]
+struct Frontier<C> {
+    nodes: Vec<Node<C>>,
+    states: FxHashMap<Rc<Vec<C>>, usize>,
+    layer: Vec<usize>,
+}
+
+impl<C: Cell> Frontier<C> {
+    fn push_state(&mut self, tubes: Vec<C>, parent: usize, depth: usize) -> Option<usize> {
+        if self.states.contains_key(&tubes) {
+            return None;
+        }
+        let index = self.nodes.len();
+        let tubes = Rc::new(tubes);
+        self.nodes.push(Node {
+            tubes: tubes.clone(),
+            parent,
+            depth,
+        });
+        self.states.insert(tubes, index);
+        self.layer.push(index);
+        Some(index)
+    }
+
+    fn get_path(&self, mut index: usize) -> Vec<&[C]> {
+        let mut path = vec![];
+        loop {
+            let node = &self.nodes[index];
+            path.push(node.tubes.as_slice());
+            if node.depth == 0 {
+                return path;
+            }
+            index = node.parent;
+        }
+    }
+}
+
+pub struct BidirectionalSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    forward: Frontier<C>,
+    backward: Frontier<C>,
+    meeting: Option<(usize, usize)>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for BidirectionalSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            initial_tubes,
+            forward: Frontier::default(),
+            backward: Frontier::default(),
+            meeting: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        let Some(solved_state) = get_solved_state(&self.initial_tubes, self.height, self.tubes)
+        else {
+            return SearchOutcome::Unsolvable;
+        };
+        let (_, sorted_tubes) = get_transform(&self.initial_tubes, self.height, self.tubes);
+        self.forward.push_state(sorted_tubes, usize::MAX, 0);
+        self.backward.push_state(solved_state, usize::MAX, 0);
+        if let Some(&index) = self.backward.states.get(&self.forward.nodes[0].tubes) {
+            self.meeting = Some((0, index));
+            return SearchOutcome::Solved;
+        }
+        while !self.forward.layer.is_empty() && !self.backward.layer.is_empty() {
+            let is_forward = self.forward.layer.len() <= self.backward.layer.len();
+            match self.expand_layer(is_forward) {
+                Ok(true) => return SearchOutcome::Solved,
+                Ok(false) => {}
+                Err(outcome) => return outcome,
+            }
+        }
+        SearchOutcome::Unsolvable
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let (forward_index, backward_index) = self.meeting.unwrap();
+        let mut states = self.forward.get_path(forward_index);
+        states.reverse();
+        states.extend(self.backward.get_path(backward_index).into_iter().skip(1));
+        get_steps_between(&self.initial_tubes, self.height, self.tubes, &states[1..])
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> BidirectionalSolver<C> {
+    fn expand_layer(&mut self, is_forward: bool) -> Result<bool, SearchOutcome> {
+        let (frontier, other) = if is_forward {
+            (&mut self.forward, &self.backward)
+        } else {
+            (&mut self.backward, &self.forward)
+        };
+        let layer = std::mem::take(&mut frontier.layer);
+        let mut best: Option<(usize, usize, usize)> = None;
+        for index in layer {
+            self.control
+                .expand(1, frontier.nodes.len() + other.nodes.len())?;
+            let state = frontier.nodes[index].tubes.clone();
+            let depth = frontier.nodes[index].depth;
+            let tube_stats: Vec<TubeStats> = state
+                .chunks_exact(self.height)
+                .map(|tube| get_tube_stat(tube, self.height))
+                .collect();
+            let moves = if is_forward {
+                get_legal_moves(&tube_stats, self.height)
+            } else {
+                get_reverse_moves(&tube_stats, self.height)
+            };
+            for (from, to, amount) in moves {
+                let mut tubes = (*state).clone();
+                if is_forward {
+                    pour(&mut tubes, self.height, &tube_stats, from, to, amount);
+                } else {
+                    pour_back(&mut tubes, self.height, from, to, amount);
+                }
+                let (_, sorted_tubes) = get_transform(&tubes, self.height, self.tubes);
+                let Some(child) = frontier.push_state(sorted_tubes, index, depth + 1) else {
+                    self.control.record_duplicates(1);
+                    continue;
+                };
+                if let Some(&other_index) = other.states.get(&frontier.nodes[child].tubes) {
+                    let length = depth + 1 + other.nodes[other_index].depth;
+                    if best.is_none_or(|(best_length, _, _)| length < best_length) {
+                        best = Some((length, child, other_index));
+                    }
+                }
+            }
+        }
+        if let Some((_, child, other_index)) = best {
+            self.meeting = Some(if is_forward {
+                (child, other_index)
+            } else {
+                (other_index, child)
+            });
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
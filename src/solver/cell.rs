@@ -0,0 +1,37 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+/// The integer type storing one cell of a tube: 0 for an empty cell, or a color numbered from 1.
+///
+/// `u8` covers puzzles of up to 255 colors and keeps states small, `u16` is for larger ones.
+pub trait Cell: Copy + Default + Ord + Hash + Debug + Display + Send + Sync + 'static {
+    const EMPTY: Self;
+    /// Largest color number the type can hold.
+    const MAX_COLOR: usize;
+
+    /// Converts a color number, which must not exceed [`Cell::MAX_COLOR`].
+    fn from_color(color: usize) -> Self;
+    fn color(self) -> usize;
+}
+
+macro_rules! impl_cell {
+    ($($t:ty),*) => {
+        $(
+            impl Cell for $t {
+                const EMPTY: Self = 0;
+                const MAX_COLOR: usize = <$t>::MAX as usize;
+
+                fn from_color(color: usize) -> Self {
+                    debug_assert!(color <= Self::MAX_COLOR);
+                    color as $t
+                }
+
+                fn color(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_cell!(u8, u16);
@@ -0,0 +1,36 @@
+/// Which pours a tube may take part in, on top of its capacity.
+///
+/// Constraints are ordered so that sorted states can list tubes of the same constraint next to
+/// each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TubeConstraint {
+    #[default]
+    Free,
+    /// Only accepts pours of the given color.
+    SingleColor(usize),
+    /// Takes part in no pour until the given number of tubes are completed.
+    Locked { completed: usize },
+    /// Can be poured from but never into.
+    SourceOnly,
+    /// Can be poured into but never from.
+    SinkOnly,
+}
+
+impl TubeConstraint {
+    /// Whether the tube is still locked while `completed` tubes are completed.
+    pub fn is_locked(self, completed: usize) -> bool {
+        matches!(self, TubeConstraint::Locked { completed: needed } if completed < needed)
+    }
+
+    pub fn can_pour_from(self, completed: usize) -> bool {
+        self != TubeConstraint::SinkOnly && !self.is_locked(completed)
+    }
+
+    pub fn can_pour_into(self, color: usize, completed: usize) -> bool {
+        match self {
+            TubeConstraint::SingleColor(only) => color == only,
+            TubeConstraint::SourceOnly => false,
+            _ => !self.is_locked(completed),
+        }
+    }
+}
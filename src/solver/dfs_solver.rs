@@ -1,7 +1,13 @@
 use rustc_hash::FxHashSet;
 
+use super::cell::Cell;
+use super::constraint::TubeConstraint;
+use super::game_state::GameState;
+use super::packed_state::{PackedState, StatePacker};
+use super::rules::RuleSet;
+use super::search::SearchControl;
 use super::utils::*;
-use super::{SolutionStep, Solver};
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
 
 #[derive(Clone)]
 struct State {
@@ -10,94 +16,176 @@ struct State {
     transform: Vec<usize>,
 }
 
-pub struct DFSSolver {
+pub struct DFSSolver<C: Cell = u8> {
     height: usize,
+    /// Number of cells between the starts of consecutive tubes, the largest capacity.
+    stride: usize,
     tubes: usize,
-    initial_tubes: Vec<u8>,
-    states: FxHashSet<Vec<u8>>,
+    initial_tubes: Vec<C>,
+    initial_capacities: Vec<usize>,
+    initial_constraints: Vec<TubeConstraint>,
+    /// The capacities and constraints in sorted tube order, which is the same for every sorted
+    /// state.
+    capacities: Vec<usize>,
+    constraints: Vec<TubeConstraint>,
+    rules: RuleSet,
+    packer: StatePacker,
+    states: FxHashSet<PackedState>,
     stack: Vec<State>,
+    control: SearchControl,
 }
 
-impl Solver for DFSSolver {
-    fn new(height: usize, initial_tubes: Vec<u8>) -> Self {
+impl<C: Cell> Solver<C> for DFSSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        let tubes = initial_tubes.len() / height;
+        Self::with_capacities(height, vec![height; tubes], initial_tubes)
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        match self.inner_search(&self.initial_tubes.clone(), usize::MAX, usize::MAX) {
+            Ok(Subtree::Solved) => SearchOutcome::Solved,
+            Ok(Subtree::Exhausted | Subtree::Exceeded(_)) => SearchOutcome::Unsolvable,
+            Err(outcome) => outcome,
+        }
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let moves = get_moves_from_transforms(
+            self.tubes,
+            self.stack
+                .iter()
+                .map(|state| (state.from, state.to, state.transform.as_slice())),
+        );
+        let mut current = GameState::with_capacities(
+            self.height,
+            self.initial_capacities.clone(),
+            self.initial_tubes.clone(),
+        );
+        current.set_rules(self.rules);
+        current.set_constraints(self.initial_constraints.clone());
+        for mv in moves {
+            current.apply(mv).unwrap();
+        }
+        current.history().to_vec()
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> DFSSolver<C> {
+    /// Creates a solver for tubes of individual capacities, laid out as in
+    /// [`GameState::with_capacities`].
+    pub fn with_capacities(height: usize, capacities: Vec<usize>, initial_tubes: Vec<C>) -> Self {
+        let mut sorted_capacities = capacities.clone();
+        sorted_capacities.sort_unstable();
+        let constraints = vec![TubeConstraint::Free; capacities.len()];
         Self {
             height,
-            tubes: initial_tubes.len() / height,
+            stride: capacities.iter().copied().max().unwrap_or(height),
+            tubes: capacities.len(),
+            packer: StatePacker::new(&initial_tubes),
             initial_tubes,
+            initial_capacities: capacities,
+            initial_constraints: constraints.clone(),
+            capacities: sorted_capacities,
+            constraints,
+            rules: RuleSet::default(),
             states: FxHashSet::default(),
             stack: vec![],
+            control: SearchControl::new(),
         }
     }
 
-    fn search(&mut self) -> bool {
-        self.inner_search(&self.initial_tubes.clone(), usize::MAX, usize::MAX)
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
     }
 
-    fn get_solution(&self) -> Vec<SolutionStep> {
-        let mut solution = vec![];
-        let mut transform: Vec<usize> = (0..self.tubes).collect();
-        let mut new_transform = vec![0; self.tubes];
-        for (index, step) in self.stack.iter().enumerate() {
-            if index > 0 {
-                solution.push(SolutionStep {
-                    from: transform[step.from],
-                    to: transform[step.to],
-                });
-            }
-            for i in 0..self.tubes {
-                new_transform[i] = transform[step.transform[i]];
-            }
-            std::mem::swap(&mut transform, &mut new_transform);
-        }
-        solution
+    /// Restricts the pours each tube takes part in, one constraint per tube, see
+    /// [`GameState::set_constraints`].
+    pub fn set_constraints(&mut self, constraints: Vec<TubeConstraint>) {
+        assert_eq!(constraints.len(), self.tubes);
+        (self.capacities, self.constraints) =
+            sort_tube_kinds(&self.initial_capacities, &constraints);
+        self.initial_constraints = constraints;
     }
-}
 
-impl DFSSolver {
-    fn inner_search(&mut self, state: &[u8], from: usize, to: usize) -> bool {
-        let (transform, sorted_tubes) = get_transform(state, self.height, self.tubes);
-        if self.states.contains(&sorted_tubes) {
-            return false;
+    /// Searches the subtree of `state`, failing only when the budget stops the search.
+    fn inner_search(
+        &mut self,
+        state: &[C],
+        from: usize,
+        to: usize,
+    ) -> Result<Subtree, SearchOutcome> {
+        // Only the initial state is not in sorted tube order.
+        let (capacities, constraints) = if self.stack.is_empty() {
+            (&self.initial_capacities, &self.initial_constraints)
+        } else {
+            (&self.capacities, &self.constraints)
+        };
+        let (transform, sorted_tubes) =
+            get_capacity_transform(state, self.stride, capacities, constraints);
+        if !self.states.insert(self.packer.pack(&sorted_tubes)) {
+            self.control.record_duplicates(1);
+            return Ok(Subtree::Exhausted);
         }
-        self.states.insert(sorted_tubes.clone());
         self.stack.push(State {
             from,
             to,
             transform,
         });
-        if is_solved(&sorted_tubes, self.height) {
-            return true;
+        self.control.record_stack_depth(self.stack.len());
+        if is_goal(&sorted_tubes, self.stride, self.height) {
+            return Ok(Subtree::Solved);
         }
-        let tube_stats: Vec<TubeStats> = sorted_tubes
-            .chunks_exact(self.height)
-            .map(|tube| get_tube_stat(tube, self.height))
-            .collect();
+        self.control.expand(1, self.states.len())?;
+        let tube_stats = get_capacity_stats(
+            &sorted_tubes,
+            self.stride,
+            &self.capacities,
+            &self.constraints,
+        );
+        let completed = get_completed_tubes(&tube_stats, self.height);
+        let uniform = tube_stats
+            .iter()
+            .all(|stat| stat.capacity == self.height && stat.constraint == TubeConstraint::Free);
+        // Merging two single-colored tubes never loses a solution: a whole run leaves an empty
+        // tube behind, and a single unit can be moved back. Tubes of other capacities may not
+        // hold the merge, and constrained ones may not take the pours back, so nothing is forced
+        // then.
         for i in 0..(self.tubes - 1) {
-            if !tube_stats[i].simple || tube_stats[i].color_height == self.height {
+            if !uniform || !tube_stats[i].simple || tube_stats[i].color_height == self.height {
                 continue;
             }
             for j in (i + 1)..self.tubes {
                 if tube_stats[j].simple && tube_stats[i].color == tube_stats[j].color {
                     let mut tubes = sorted_tubes;
-                    pour(
-                        &mut tubes,
-                        self.height,
-                        &tube_stats,
-                        i,
-                        j,
-                        tube_stats[i].size,
-                    );
-                    if self.inner_search(&tubes, i, j) {
-                        return true;
-                    } else {
-                        self.stack.pop();
-                        return false;
+                    let amount = self.rules.amount(tube_stats[i].size, self.height);
+                    pour(&mut tubes, self.stride, &tube_stats, i, j, amount);
+                    if self.inner_search(&tubes, i, j)? == Subtree::Solved {
+                        return Ok(Subtree::Solved);
                     }
+                    self.stack.pop();
+                    return Ok(Subtree::Exhausted);
                 }
             }
         }
         for i in 0..self.tubes {
-            if !tube_stats[i].simple || tube_stats[i].color_height == self.height {
+            if !uniform
+                || self.rules != RuleSet::PourRun
+                || !tube_stats[i].simple
+                || tube_stats[i].color_height == self.height
+            {
                 continue;
             }
             for j in 0..self.tubes {
@@ -107,55 +195,71 @@ impl DFSSolver {
                 let amount = tube_stats[j].color_height;
                 if tube_stats[i].color_height + amount == self.height {
                     let mut tubes = sorted_tubes;
-                    pour(&mut tubes, self.height, &tube_stats, j, i, amount);
-                    if self.inner_search(&tubes, j, i) {
-                        return true;
-                    } else {
-                        self.stack.pop();
-                        return false;
+                    pour(&mut tubes, self.stride, &tube_stats, j, i, amount);
+                    if self.inner_search(&tubes, j, i)? == Subtree::Solved {
+                        return Ok(Subtree::Solved);
                     }
+                    self.stack.pop();
+                    return Ok(Subtree::Exhausted);
                 }
             }
         }
-        for i in 0..(self.tubes - 1) {
-            if tube_stats[i].color_height == self.height {
+        let height = self.height;
+        let is_completed = |stat: &TubeStats| stat.simple && stat.color_height == height;
+        for i in 0..self.tubes {
+            if is_completed(&tube_stats[i]) {
                 continue;
             } else if tube_stats[i].color_height == 0 {
-                if i > 0 {
+                // Empty tubes of equal capacity and constraint are interchangeable and sorted next
+                // to each other.
+                if i > 0
+                    && tube_stats[i - 1].color_height == 0
+                    && tube_stats[i - 1].capacity == tube_stats[i].capacity
+                    && tube_stats[i - 1].constraint == tube_stats[i].constraint
+                {
                     continue;
                 }
-                for j in (i + 1)..self.tubes {
-                    if tube_stats[j].simple || tube_stats[j].color_height == 0 {
+                for j in 0..self.tubes {
+                    if j == i
+                        || tube_stats[j].color_height == 0
+                        || (tube_stats[j].simple
+                            && tube_stats[j].capacity == tube_stats[i].capacity
+                            && tube_stats[j].constraint == tube_stats[i].constraint)
+                        || !can_pour(&tube_stats[j], &tube_stats[i], completed)
+                    {
                         continue;
                     }
                     let mut tubes = sorted_tubes.clone();
-                    let amount = tube_stats[j].color_height;
-                    pour(&mut tubes, self.height, &tube_stats, j, i, amount);
-                    if self.inner_search(&tubes, j, i) {
-                        return true;
+                    let amount = self
+                        .rules
+                        .amount(tube_stats[j].color_height, tube_stats[i].capacity);
+                    pour(&mut tubes, self.stride, &tube_stats, j, i, amount);
+                    if self.inner_search(&tubes, j, i)? == Subtree::Solved {
+                        return Ok(Subtree::Solved);
                     }
                 }
             } else {
                 for j in (i + 1)..self.tubes {
-                    if tube_stats[j].color_height < self.height
-                        && tube_stats[i].color == tube_stats[j].color
-                    {
+                    if !is_completed(&tube_stats[j]) && tube_stats[i].color == tube_stats[j].color {
                         let mut indexes = vec![];
-                        if tube_stats[j].size < self.height {
+                        if tube_stats[j].size < tube_stats[j].capacity {
                             indexes.push((i, j));
                         }
-                        if tube_stats[i].size < self.height {
+                        if tube_stats[i].size < tube_stats[i].capacity {
                             indexes.push((j, i));
                         }
                         for (x, y) in indexes {
+                            if !can_pour(&tube_stats[x], &tube_stats[y], completed) {
+                                continue;
+                            }
                             let mut tubes = sorted_tubes.clone();
-                            let amount = usize::min(
+                            let amount = self.rules.amount(
                                 tube_stats[x].color_height,
-                                self.height - tube_stats[y].size,
+                                tube_stats[y].capacity - tube_stats[y].size,
                             );
-                            pour(&mut tubes, self.height, &tube_stats, x, y, amount);
-                            if self.inner_search(&tubes, x, y) {
-                                return true;
+                            pour(&mut tubes, self.stride, &tube_stats, x, y, amount);
+                            if self.inner_search(&tubes, x, y)? == Subtree::Solved {
+                                return Ok(Subtree::Solved);
                             }
                         }
                     }
@@ -163,6 +267,44 @@ impl DFSSolver {
             }
         }
         self.stack.pop();
-        false
+        Ok(Subtree::Exhausted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::simulate::simulate_from;
+
+    fn assert_solves(height: usize, capacities: Vec<usize>, tubes: &[&str]) {
+        let tubes: Vec<u8> = parse_tubes(tubes, 5);
+        let mut solver = DFSSolver::with_capacities(height, capacities.clone(), tubes.clone());
+        assert!(solver.search().is_solved());
+        let state = GameState::with_capacities(height, capacities, tubes);
+        assert!(simulate_from(state, &solver.get_solution()).unwrap().solved);
+    }
+
+    #[test]
+    fn capacities() {
+        assert_solves(3, vec![5, 2, 5, 5], &["b", "c c", "b a a a", "b c"]);
+        assert_solves(4, vec![4, 5, 3, 4], &["b c a a", "b a b a c", "c b c", ""]);
+    }
+
+    #[test]
+    fn capacities_and_constraints() {
+        let tubes: Vec<u8> = parse_tubes(&["a b a", "b a b", "", ""], 4);
+        let capacities = vec![3, 3, 4, 2];
+        let constraints = vec![
+            TubeConstraint::Free,
+            TubeConstraint::Free,
+            TubeConstraint::SinkOnly,
+            TubeConstraint::Free,
+        ];
+        let mut solver = DFSSolver::with_capacities(3, capacities.clone(), tubes.clone());
+        solver.set_constraints(constraints.clone());
+        assert!(solver.search().is_solved());
+        let mut state = GameState::with_capacities(3, capacities, tubes);
+        state.set_constraints(constraints);
+        assert!(simulate_from(state, &solver.get_solution()).unwrap().solved);
     }
 }
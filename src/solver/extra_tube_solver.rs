@@ -0,0 +1,320 @@
+use rustc_hash::FxHashSet;
+
+use super::cell::Cell;
+use super::game_state::{GameState, Move};
+use super::packed_state::{PackedState, StatePacker};
+use super::rules::RuleSet;
+use super::search::SearchControl;
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+/// Number of empty tubes [`ExtraTubeSolver`] may add, as the extra tube power-up does once.
+pub const DEFAULT_MAX_EXTRA_TUBES: usize = 1;
+
+/// Marks a state reached by adding an empty tube rather than by a pour.
+const ADD_TUBE: u16 = u16::MAX;
+
+/// A visited state, as the pour from its parent given in the parent's sorted tube order.
+#[derive(Clone)]
+struct State {
+    parent: u32,
+    from: u16,
+    to: u16,
+}
+
+/// Breadth-first search where adding an empty tube is a move costing more than any number of
+/// pours, so solutions add as few tubes as possible, then pour as few times as possible.
+///
+/// States are searched in layers of the same number of added tubes, each holding one more tube
+/// than the last. Layer `k + 1` only starts once layer `k` is exhausted, from each of its states
+/// at the depth it was reached. Among the shortest solutions, the one adding its last tube after
+/// the most pours is returned, which tells the latest point at which the tube is needed.
+pub struct ExtraTubeSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    max_extra_tubes: usize,
+    rules: RuleSet,
+    nodes: Vec<State>,
+    goal: Option<u32>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for ExtraTubeSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            initial_tubes,
+            max_extra_tubes: DEFAULT_MAX_EXTRA_TUBES,
+            rules: RuleSet::default(),
+            nodes: vec![],
+            goal: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        // The states of the previous layer, by depth, which the next layer starts from.
+        let mut previous: Vec<Vec<(u32, PackedState)>> = vec![];
+        let mut previous_packer = StatePacker::new(&self.initial_tubes);
+        for extra_tubes in 0..=self.max_extra_tubes {
+            let tube_count = self.tubes + extra_tubes;
+            let mut tubes = self.initial_tubes.clone();
+            tubes.resize(tube_count * self.height, C::EMPTY);
+            let mut layer = Layer {
+                tube_count,
+                packer: StatePacker::new(&tubes),
+                states: FxHashSet::default(),
+                frontier: vec![],
+                depths: vec![],
+            };
+            let solved = if extra_tubes == 0 {
+                self.push_state(&mut layer, &tubes, u32::MAX, 0, 0, 0)
+            } else {
+                self.seed_states(&mut layer, &previous, previous_packer, 0)
+            };
+            if solved {
+                return SearchOutcome::Solved;
+            }
+            let mut current = std::mem::take(&mut layer.frontier);
+            let mut depth = 0;
+            while !current.is_empty() || depth + 1 < previous.len() {
+                // States reached by adding a tube come first, so they are kept over the same
+                // states reached by pours after an earlier addition.
+                if self.seed_states(&mut layer, &previous, previous_packer, depth + 1) {
+                    return SearchOutcome::Solved;
+                }
+                for (index, state) in current.iter() {
+                    if let Err(outcome) = self.control.expand(1, self.nodes.len()) {
+                        return outcome;
+                    }
+                    layer.packer.unpack(state, &mut tubes);
+                    if self.expand_state(&mut layer, &tubes, *index, depth + 1) {
+                        return SearchOutcome::Solved;
+                    }
+                }
+                let next = std::mem::take(&mut layer.frontier);
+                let expanded = std::mem::replace(&mut current, next);
+                if extra_tubes < self.max_extra_tubes {
+                    layer.depths.push(expanded);
+                }
+                depth += 1;
+            }
+            previous = layer.depths;
+            previous_packer = layer.packer;
+        }
+        SearchOutcome::Unsolvable
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        self.replay().0.history().to_vec()
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+/// The states of a layer, all holding the same number of tubes.
+struct Layer {
+    tube_count: usize,
+    packer: StatePacker,
+    states: FxHashSet<PackedState>,
+    /// The states reached at the depth being expanded.
+    frontier: Vec<(u32, PackedState)>,
+    /// The states expanded so far, by depth.
+    depths: Vec<Vec<(u32, PackedState)>>,
+}
+
+impl<C: Cell> ExtraTubeSolver<C> {
+    /// Sets the number of empty tubes the solution may add, defaulting to
+    /// [`DEFAULT_MAX_EXTRA_TUBES`].
+    pub fn set_max_extra_tubes(&mut self, max_extra_tubes: usize) {
+        self.max_extra_tubes = max_extra_tubes;
+    }
+
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    /// Number of pours played before each empty tube is added to the solution.
+    ///
+    /// Added tubes come after the initial ones, so the first one is numbered as many as there are
+    /// initial tubes in the moves of [`Solver::get_solution`].
+    pub fn get_extra_tubes(&self) -> Vec<usize> {
+        self.replay().1
+    }
+
+    /// Plays the solution from the initial tubes, returning the final position and the number of
+    /// pours before each added tube.
+    fn replay(&self) -> (GameState<C>, Vec<usize>) {
+        let mut moves = vec![];
+        let mut index = self.goal.unwrap();
+        while index > 0 {
+            let state = &self.nodes[index as usize];
+            moves.push((state.from, state.to));
+            index = state.parent;
+        }
+        moves.reverse();
+        let mut current = GameState::new(self.height, self.initial_tubes.clone());
+        current.set_rules(self.rules);
+        let mut extra_tubes = vec![];
+        for (from, to) in moves {
+            if from == ADD_TUBE {
+                extra_tubes.push(current.history().len());
+                current.add_empty_tube();
+                continue;
+            }
+            let transform = current.sorted_transform();
+            current
+                .apply(Move {
+                    from: transform[from as usize],
+                    to: transform[to as usize],
+                })
+                .unwrap();
+        }
+        (current, extra_tubes)
+    }
+
+    /// Adds an empty tube to the states of the previous layer reached at `depth`.
+    fn seed_states(
+        &mut self,
+        layer: &mut Layer,
+        previous: &[Vec<(u32, PackedState)>],
+        previous_packer: StatePacker,
+        depth: usize,
+    ) -> bool {
+        let Some(states) = previous.get(depth) else {
+            return false;
+        };
+        // The added tube stays empty while the others are unpacked.
+        let mut tubes = vec![C::EMPTY; layer.tube_count * self.height];
+        let previous_cells = (layer.tube_count - 1) * self.height;
+        for (index, state) in states.iter() {
+            previous_packer.unpack(state, &mut tubes[..previous_cells]);
+            let (from, to) = (ADD_TUBE as usize, ADD_TUBE as usize);
+            if self.push_state(layer, &tubes, *index, depth, from, to) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn expand_state(&mut self, layer: &mut Layer, state: &[C], index: u32, depth: usize) -> bool {
+        let tube_stats: Vec<TubeStats> = state
+            .chunks_exact(self.height)
+            .map(|tube| get_tube_stat(tube, self.height))
+            .collect();
+        for (from, to, amount) in get_bfs_moves(&tube_stats, self.height, self.rules) {
+            let mut tubes = state.to_vec();
+            pour(&mut tubes, self.height, &tube_stats, from, to, amount);
+            if self.push_state(layer, &tubes, index, depth, from, to) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn push_state(
+        &mut self,
+        layer: &mut Layer,
+        tubes: &[C],
+        parent: u32,
+        depth: usize,
+        from: usize,
+        to: usize,
+    ) -> bool {
+        let (_, sorted_tubes) = get_transform(tubes, self.height, layer.tube_count);
+        let packed_tubes = layer.packer.pack(&sorted_tubes);
+        if layer.states.contains(&packed_tubes) {
+            self.control.record_duplicates(1);
+            return false;
+        }
+        self.control.record_frontier(depth, 1);
+        let index = self.nodes.len() as u32;
+        self.nodes.push(State {
+            parent,
+            from: from as u16,
+            to: to as u16,
+        });
+        layer.frontier.push((index, packed_tubes.clone()));
+        layer.states.insert(packed_tubes);
+        if is_solved(&sorted_tubes, self.height) {
+            self.goal = Some(index);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::bfs_solver::BFSSolver;
+
+    /// Solves `tubes` allowing one added tube, checking that the solution plays out with the
+    /// tubes added where reported, and returns its length and the add points.
+    fn solve(height: usize, tubes: &[&str]) -> Option<(usize, Vec<usize>)> {
+        let tubes = parse_tubes(tubes, height);
+        let mut solver = ExtraTubeSolver::new(height, tubes.clone());
+        if !solver.search().is_solved() {
+            return None;
+        }
+        let steps = solver.get_solution();
+        let extra_tubes = solver.get_extra_tubes();
+        let mut game = GameState::new(height, tubes);
+        for (index, &step) in steps.iter().enumerate() {
+            for _ in extra_tubes.iter().filter(|&&pours| pours == index) {
+                game.add_empty_tube();
+            }
+            game.apply_step(step).unwrap();
+        }
+        assert!(game.is_solved());
+        Some((steps.len(), extra_tubes))
+    }
+
+    #[test]
+    fn no_tube_when_solvable() {
+        let tubes = ["a b c", "c a b", "b c a", "", ""];
+        let mut solver = BFSSolver::new(3, parse_tubes(&tubes, 3));
+        assert!(solver.search().is_solved());
+        assert_eq!(
+            solve(3, &tubes),
+            Some((solver.get_solution().len(), vec![]))
+        );
+    }
+
+    #[test]
+    fn tube_added_first_when_stuck() {
+        assert_eq!(solve(2, &["a b", "b a"]), Some((3, vec![0])));
+    }
+
+    #[test]
+    fn tube_added_as_late_as_possible() {
+        let tubes = ["a c", "a b", "a b c", "c b"];
+        let mut solver = BFSSolver::new(3, parse_tubes(&tubes, 3));
+        assert_eq!(solver.search(), SearchOutcome::Unsolvable);
+        assert_eq!(solve(3, &tubes), Some((7, vec![3])));
+    }
+
+    #[test]
+    fn limited_tubes() {
+        let mut solver = ExtraTubeSolver::new(2, parse_tubes(&["a b", "b a"], 2));
+        solver.set_max_extra_tubes(0);
+        assert_eq!(solver.search(), SearchOutcome::Unsolvable);
+        let mut solver = ExtraTubeSolver::new(2, parse_tubes(&["a b", "b a"], 2));
+        solver.set_max_extra_tubes(2);
+        assert!(solver.search().is_solved());
+        assert_eq!(solver.get_extra_tubes(), vec![0]);
+    }
+}
@@ -0,0 +1,497 @@
+use std::fmt;
+
+use super::SolutionStep;
+use super::cell::Cell;
+use super::constraint::TubeConstraint;
+use super::puzzle::{PuzzleError, validate_capacities, validate_puzzle};
+use super::rules::RuleSet;
+use super::utils::*;
+
+/// A pour from one tube into another, with tubes numbered from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+}
+
+impl From<SolutionStep> for Move {
+    fn from(step: SolutionStep) -> Self {
+        Self {
+            from: step.from,
+            to: step.to,
+        }
+    }
+}
+
+/// Why a pour breaks the game rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    InvalidTube,
+    SameTube,
+    EmptySource,
+    FullTarget,
+    ColorMismatch,
+    /// The top cell of the source is hidden, see [`super::MysterySession`].
+    HiddenSource,
+    /// A tube is locked, see [`TubeConstraint::Locked`].
+    LockedTube,
+    /// The source is sink-only or the target is source-only.
+    OneWayTube,
+    /// The target only accepts another color, see [`TubeConstraint::SingleColor`].
+    ColorRestricted,
+    /// A recorded step pours more cells than the source or the target allow, or none.
+    InvalidAmount,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::InvalidTube => write!(f, "tube does not exist"),
+            MoveError::SameTube => write!(f, "source and target are the same tube"),
+            MoveError::EmptySource => write!(f, "source tube is empty"),
+            MoveError::FullTarget => write!(f, "target tube is full"),
+            MoveError::ColorMismatch => write!(f, "top colors do not match"),
+            MoveError::HiddenSource => write!(f, "top cell of the source tube is hidden"),
+            MoveError::LockedTube => write!(f, "tube is locked"),
+            MoveError::OneWayTube => write!(f, "tube cannot be poured that way"),
+            MoveError::ColorRestricted => write!(f, "target tube only accepts another color"),
+            MoveError::InvalidAmount => write!(f, "amount cannot be poured"),
+        }
+    }
+}
+
+/// A puzzle position: `height * tube_count` cells listed tube by tube, bottom first, where 0 is
+/// an empty cell and colors are numbered from 1.
+///
+/// Pours move as much of the top color as the [`RuleSet`] allows, by default all that fits into
+/// the target, and are recorded so they can be undone.
+///
+/// Tubes may also have individual capacities, see [`GameState::with_capacities`], and
+/// constraints, see [`GameState::set_constraints`].
+///
+/// The solvers do not search through `GameState`: they generate and play moves on sorted raw
+/// cells, which is much faster, and only replay the solution they find on a `GameState`, so its
+/// steps hold the same tube indexes and amounts as [`GameState::history`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameState<C: Cell = u8> {
+    height: usize,
+    stride: usize,
+    capacities: Vec<usize>,
+    constraints: Vec<TubeConstraint>,
+    tube_count: usize,
+    cells: Vec<C>,
+    rules: RuleSet,
+    history: Vec<SolutionStep>,
+}
+
+impl<C: Cell> GameState<C> {
+    /// Wraps `cells` without checking them, see [`GameState::try_new`].
+    pub fn new(height: usize, cells: Vec<C>) -> Self {
+        let tube_count = cells.len() / height;
+        Self {
+            height,
+            stride: height,
+            capacities: vec![height; tube_count],
+            constraints: vec![TubeConstraint::Free; tube_count],
+            tube_count,
+            cells,
+            rules: RuleSet::default(),
+            history: vec![],
+        }
+    }
+
+    pub fn try_new(height: usize, cells: Vec<C>) -> Result<Self, PuzzleError> {
+        validate_puzzle(height, &cells)?;
+        Ok(Self::new(height, cells))
+    }
+
+    /// Wraps tubes of individual capacities without checking them, see
+    /// [`GameState::try_with_capacities`].
+    ///
+    /// Each color has `height` cells and is sorted once they fill a single tube. The cells of each
+    /// tube are stored as many cells apart as the largest capacity, padded with empty cells.
+    pub fn with_capacities(height: usize, capacities: Vec<usize>, cells: Vec<C>) -> Self {
+        Self {
+            height,
+            stride: capacities.iter().copied().max().unwrap_or(height),
+            tube_count: capacities.len(),
+            constraints: vec![TubeConstraint::Free; capacities.len()],
+            capacities,
+            cells,
+            rules: RuleSet::default(),
+            history: vec![],
+        }
+    }
+
+    pub fn try_with_capacities(
+        height: usize,
+        capacities: Vec<usize>,
+        cells: Vec<C>,
+    ) -> Result<Self, PuzzleError> {
+        validate_capacities(height, &capacities, &cells)?;
+        Ok(Self::with_capacities(height, capacities, cells))
+    }
+
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    /// Restricts the pours each tube takes part in, one constraint per tube.
+    pub fn set_constraints(&mut self, constraints: Vec<TubeConstraint>) {
+        assert_eq!(constraints.len(), self.tube_count);
+        self.constraints = constraints;
+    }
+
+    pub fn constraints(&self) -> &[TubeConstraint] {
+        &self.constraints
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn tube_count(&self) -> usize {
+        self.tube_count
+    }
+
+    pub fn cells(&self) -> &[C] {
+        &self.cells
+    }
+
+    pub fn capacities(&self) -> &[usize] {
+        &self.capacities
+    }
+
+    /// The cells of a tube, bottom first, as many as it can hold.
+    pub fn tube(&self, index: usize) -> &[C] {
+        let offset = index * self.stride;
+        &self.cells[offset..offset + self.capacities[index]]
+    }
+
+    /// Number of filled cells in a tube.
+    pub fn size(&self, index: usize) -> usize {
+        self.tube_stat(index).size
+    }
+
+    pub fn free_space(&self, index: usize) -> usize {
+        self.capacities[index] - self.size(index)
+    }
+
+    pub fn top_color(&self, index: usize) -> Option<C> {
+        let stat = self.tube_stat(index);
+        (stat.size > 0).then(|| C::from_color(stat.color))
+    }
+
+    /// Number of cells of the top color at the top of a tube.
+    pub fn top_run(&self, index: usize) -> usize {
+        self.tube_stat(index).color_height
+    }
+
+    pub fn is_solved(&self) -> bool {
+        is_goal(&self.cells, self.stride, self.height)
+    }
+
+    /// The pours applied so far, oldest first.
+    pub fn history(&self) -> &[SolutionStep] {
+        &self.history
+    }
+
+    /// Checks a pour against the game rules and returns the number of cells it moves.
+    pub fn check(&self, mv: Move) -> Result<usize, MoveError> {
+        if mv.from >= self.tube_count || mv.to >= self.tube_count {
+            return Err(MoveError::InvalidTube);
+        }
+        if mv.from == mv.to {
+            return Err(MoveError::SameTube);
+        }
+        let (from, to) = (self.tube_stat(mv.from), self.tube_stat(mv.to));
+        if from.size == 0 {
+            return Err(MoveError::EmptySource);
+        }
+        if to.size == to.capacity {
+            return Err(MoveError::FullTarget);
+        }
+        if to.size > 0 && to.color != from.color {
+            return Err(MoveError::ColorMismatch);
+        }
+        let completed = get_completed_tubes(&self.tube_stats(), self.height);
+        if from.constraint.is_locked(completed) || to.constraint.is_locked(completed) {
+            return Err(MoveError::LockedTube);
+        }
+        if from.constraint == TubeConstraint::SinkOnly
+            || to.constraint == TubeConstraint::SourceOnly
+        {
+            return Err(MoveError::OneWayTube);
+        }
+        if !to.constraint.can_pour_into(from.color, completed) {
+            return Err(MoveError::ColorRestricted);
+        }
+        Ok(self.rules.amount(from.color_height, to.capacity - to.size))
+    }
+
+    /// Every legal pour, ordered by source then target.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let tube_stats = self.tube_stats();
+        let completed = get_completed_tubes(&tube_stats, self.height);
+        let mut moves = vec![];
+        for (from, from_stat) in tube_stats.iter().enumerate() {
+            if from_stat.size == 0 {
+                continue;
+            }
+            for (to, to_stat) in tube_stats.iter().enumerate() {
+                if to != from
+                    && to_stat.size < to_stat.capacity
+                    && (to_stat.size == 0 || to_stat.color == from_stat.color)
+                    && can_pour(from_stat, to_stat, completed)
+                {
+                    moves.push(Move { from, to });
+                }
+            }
+        }
+        moves
+    }
+
+    pub fn apply(&mut self, mv: Move) -> Result<(), MoveError> {
+        let amount = self.check(mv)?;
+        self.pour_amount(mv, amount);
+        Ok(())
+    }
+
+    /// Plays a recorded step, pouring its own amount rather than the one given by the rules.
+    pub fn apply_step(&mut self, step: SolutionStep) -> Result<(), MoveError> {
+        let mv = step.into();
+        self.check(mv)?;
+        let (from, to) = (self.tube_stat(step.from), self.tube_stat(step.to));
+        if step.amount == 0
+            || step.amount > from.color_height
+            || step.amount > to.capacity - to.size
+        {
+            return Err(MoveError::InvalidAmount);
+        }
+        self.pour_amount(mv, step.amount);
+        Ok(())
+    }
+
+    fn pour_amount(&mut self, mv: Move, amount: usize) {
+        let tube_stats = self.tube_stats();
+        pour(
+            &mut self.cells,
+            self.stride,
+            &tube_stats,
+            mv.from,
+            mv.to,
+            amount,
+        );
+        self.history.push(SolutionStep {
+            from: mv.from,
+            to: mv.to,
+            amount,
+            color: tube_stats[mv.from].color,
+        });
+    }
+
+    /// Adds an empty tube after the last one, as large as the largest tube, as the extra tube
+    /// power-up does.
+    pub fn add_empty_tube(&mut self) {
+        self.cells.resize(self.cells.len() + self.stride, C::EMPTY);
+        self.capacities.push(self.stride);
+        self.constraints.push(TubeConstraint::Free);
+        self.tube_count += 1;
+    }
+
+    /// Takes back the last applied pour, returning it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let step = self.history.pop()?;
+        pour_back(
+            &mut self.cells,
+            self.stride,
+            step.from,
+            step.to,
+            step.amount,
+        );
+        Some(step.into())
+    }
+
+    /// The sorted-to-actual tube order, as in the sorted states of the solvers.
+    pub(super) fn sorted_transform(&self) -> Vec<usize> {
+        get_capacity_transform(
+            &self.cells,
+            self.stride,
+            &self.capacities,
+            &self.constraints,
+        )
+        .0
+    }
+
+    fn tube_stat(&self, index: usize) -> TubeStats {
+        let offset = index * self.stride;
+        TubeStats {
+            capacity: self.capacities[index],
+            constraint: self.constraints[index],
+            ..get_tube_stat(&self.cells[offset..offset + self.stride], self.stride)
+        }
+    }
+
+    fn tube_stats(&self) -> Vec<TubeStats> {
+        get_capacity_stats(
+            &self.cells,
+            self.stride,
+            &self.capacities,
+            &self.constraints,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::mystery::MysterySession;
+
+    fn game(height: usize, tubes: &[&str]) -> GameState {
+        GameState::new(height, parse_tubes(tubes, height))
+    }
+
+    #[test]
+    fn apply_and_undo() {
+        let mut game = game(3, &["a b b", "a a", "b"]);
+        let initial = game.clone();
+        game.apply(Move { from: 0, to: 2 }).unwrap();
+        assert_eq!(game.tube(0), [1, 0, 0]);
+        assert_eq!(game.tube(2), [2, 2, 2]);
+        assert_eq!(
+            game.history(),
+            [SolutionStep {
+                from: 0,
+                to: 2,
+                amount: 2,
+                color: 2
+            }]
+        );
+        game.apply(Move { from: 1, to: 0 }).unwrap();
+        assert!(game.is_solved());
+        assert_eq!(game.undo(), Some(Move { from: 1, to: 0 }));
+        assert_eq!(game.undo(), Some(Move { from: 0, to: 2 }));
+        assert_eq!(game, initial);
+        assert_eq!(game.undo(), None);
+    }
+
+    #[test]
+    fn pours_what_fits() {
+        let mut game = game(3, &["a b b", "b", ""]);
+        game.apply(Move { from: 0, to: 1 }).unwrap();
+        assert_eq!(game.history()[0].amount, 2);
+        let mut game = GameState::new(3, parse_tubes(&["a b b", "", "a a b"], 3));
+        game.apply(Move { from: 0, to: 1 }).unwrap();
+        game.apply(Move { from: 2, to: 1 }).unwrap();
+        assert_eq!(game.top_run(1), 3);
+        assert_eq!(game.history()[1].amount, 1);
+        let mut game = GameState::new(3, parse_tubes(&["a b b", "", "a a b"], 3));
+        game.set_rules(RuleSet::SingleUnit);
+        game.apply(Move { from: 0, to: 1 }).unwrap();
+        assert_eq!(game.history()[0].amount, 1);
+        assert_eq!(game.undo(), Some(Move { from: 0, to: 1 }));
+        assert_eq!(game.tube(0), [1, 2, 2]);
+    }
+
+    #[test]
+    fn apply_step() {
+        let mut game = game(3, &["a b b", "b", ""]);
+        let step = |from, to, amount| SolutionStep {
+            from,
+            to,
+            amount,
+            color: 2,
+        };
+        let initial = game.clone();
+        for amount in [0, 3] {
+            assert_eq!(
+                game.apply_step(step(0, 2, amount)),
+                Err(MoveError::InvalidAmount)
+            );
+        }
+        // The target only has room for two cells.
+        assert_eq!(
+            game.apply_step(step(0, 1, 3)),
+            Err(MoveError::InvalidAmount)
+        );
+        assert_eq!(game, initial);
+        game.apply_step(step(0, 2, 1)).unwrap();
+        assert_eq!(game.tube(0), [1, 2, 0]);
+        assert_eq!(game.tube(2), [2, 0, 0]);
+        assert_eq!(game.undo(), Some(Move { from: 0, to: 2 }));
+        assert_eq!(game, initial);
+    }
+
+    #[test]
+    fn move_errors() {
+        let game = game(2, &["a b", "b a", "a", "b", ""]);
+        let error = |from, to| game.check(Move { from, to }).unwrap_err();
+        assert_eq!(error(0, 5), MoveError::InvalidTube);
+        assert_eq!(error(5, 0), MoveError::InvalidTube);
+        assert_eq!(error(1, 1), MoveError::SameTube);
+        assert_eq!(error(4, 0), MoveError::EmptySource);
+        assert_eq!(error(2, 1), MoveError::FullTarget);
+        assert_eq!(error(0, 2), MoveError::ColorMismatch);
+        let mut copy = game.clone();
+        assert_eq!(
+            copy.apply(Move { from: 0, to: 2 }),
+            Err(MoveError::ColorMismatch)
+        );
+        assert_eq!(copy, game);
+    }
+
+    #[test]
+    fn constraint_errors() {
+        let mut game = game(2, &["a b", "b a", "", "", ""]);
+        game.set_constraints(vec![
+            TubeConstraint::Free,
+            TubeConstraint::Locked { completed: 1 },
+            TubeConstraint::SourceOnly,
+            TubeConstraint::SinkOnly,
+            TubeConstraint::SingleColor(1),
+        ]);
+        let error = |from, to| game.check(Move { from, to }).unwrap_err();
+        assert_eq!(error(1, 2), MoveError::LockedTube);
+        assert_eq!(error(0, 1), MoveError::FullTarget);
+        assert_eq!(error(0, 2), MoveError::OneWayTube);
+        assert_eq!(error(0, 4), MoveError::ColorRestricted);
+        assert_eq!(game.check(Move { from: 0, to: 3 }), Ok(1));
+        let mut game = game.clone();
+        game.apply(Move { from: 0, to: 3 }).unwrap();
+        assert_eq!(
+            game.check(Move { from: 3, to: 4 }),
+            Err(MoveError::OneWayTube)
+        );
+    }
+
+    #[test]
+    fn hidden_source() {
+        // The top cell of each full tube is hidden.
+        let cells = vec![Some(1u8), None, Some(2), None, Some(0), Some(0)];
+        let mut session = MysterySession::new(2, 2, cells).unwrap();
+        assert_eq!(
+            session.play(Move { from: 0, to: 2 }),
+            Err(MoveError::HiddenSource)
+        );
+    }
+
+    #[test]
+    fn capacities() {
+        let mut game =
+            GameState::with_capacities(2, vec![3, 2, 1], parse_tubes(&["a b b", "a", ""], 3));
+        assert_eq!(game.free_space(0), 0);
+        assert_eq!(game.free_space(2), 1);
+        game.apply(Move { from: 0, to: 2 }).unwrap();
+        assert_eq!(game.history()[0].amount, 1);
+        assert_eq!(
+            game.apply(Move { from: 0, to: 2 }),
+            Err(MoveError::FullTarget)
+        );
+        game.undo();
+        assert_eq!(game.tube(0), [1, 2, 2]);
+    }
+}
@@ -0,0 +1,100 @@
+use super::anytime_solver::AnytimeSolver;
+use super::cell::Cell;
+use super::{SearchConfig, SearchOutcome, SolutionStep, Solver};
+
+/// What to play next from a position, see [`get_hint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hint {
+    /// Every tube is already sorted.
+    Solved,
+    /// The first move of a solution of `remaining` moves, which is the shortest if `optimal`.
+    Move {
+        step: SolutionStep,
+        remaining: usize,
+        optimal: bool,
+    },
+    /// No sequence of moves sorts the tubes.
+    Unsolvable,
+    /// The search stopped before finding any solution.
+    Unknown(SearchOutcome),
+}
+
+impl Hint {
+    /// Whether the position can still be solved, or `None` when the search could not tell.
+    pub fn is_solvable(&self) -> Option<bool> {
+        match self {
+            Hint::Solved | Hint::Move { .. } => Some(true),
+            Hint::Unsolvable => Some(false),
+            Hint::Unknown(_) => None,
+        }
+    }
+}
+
+/// Finds the next move from the position `tubes`.
+///
+/// A first solution is looked for with [`AnytimeSolver`], which then shortens it until it is
+/// proven optimal or the budget of `config` runs out, so the hint is bounded by that budget.
+pub fn get_hint<C: Cell>(height: usize, tubes: Vec<C>, config: SearchConfig) -> Hint {
+    let mut solver = AnytimeSolver::new(height, tubes);
+    solver.set_config(config);
+    match solver.search() {
+        SearchOutcome::Solved => {
+            let solution = solver.get_solution();
+            match solution.first() {
+                Some(&step) => Hint::Move {
+                    step,
+                    remaining: solution.len(),
+                    optimal: solver.is_optimal(),
+                },
+                None => Hint::Solved,
+            }
+        }
+        SearchOutcome::Unsolvable => Hint::Unsolvable,
+        outcome => Hint::Unknown(outcome),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::bfs_solver::BFSSolver;
+    use crate::solver::game_state::GameState;
+    use crate::solver::utils::parse_tubes;
+
+    fn optimal_length(height: usize, tubes: Vec<u8>) -> usize {
+        let mut solver = BFSSolver::new(height, tubes);
+        assert!(solver.search().is_solved());
+        solver.get_solution().len()
+    }
+
+    #[test]
+    fn first_move_of_optimal_solution() {
+        let tubes = parse_tubes(&["a b c", "c a b", "b c a", "", ""], 3);
+        let optimal = optimal_length(3, tubes.clone());
+        let Hint::Move {
+            step,
+            remaining,
+            optimal: true,
+        } = get_hint(3, tubes.clone(), SearchConfig::default())
+        else {
+            panic!("expected an optimal move");
+        };
+        assert_eq!(remaining, optimal);
+        let mut game = GameState::new(3, tubes);
+        game.apply_step(step).unwrap();
+        assert_eq!(optimal_length(3, game.cells().to_vec()), optimal - 1);
+    }
+
+    #[test]
+    fn solved_and_unsolvable() {
+        let hint = get_hint(
+            2,
+            parse_tubes(&["a a", "b b", ""], 2),
+            SearchConfig::default(),
+        );
+        assert_eq!(hint, Hint::Solved);
+        let hint = get_hint(2, parse_tubes(&["a b", "b a"], 2), SearchConfig::default());
+        assert_eq!(hint, Hint::Unsolvable);
+        assert_eq!(hint.is_solvable(), Some(false));
+    }
+}
@@ -0,0 +1,191 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::cell::Cell;
+use super::rules::RuleSet;
+use super::search::SearchControl;
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+pub const DEFAULT_TABLE_CAPACITY: usize = 1 << 20;
+
+#[derive(Clone)]
+struct State {
+    from: usize,
+    to: usize,
+    transform: Vec<usize>,
+}
+
+pub struct IDAStarSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    color_tubes: Vec<usize>,
+    table: FxHashMap<Vec<C>, usize>,
+    table_capacity: usize,
+    /// The states of the current path missing from the table, which would otherwise be searched
+    /// around in cycles.
+    path: FxHashSet<Vec<C>>,
+    stack: Vec<State>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for IDAStarSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            color_tubes: get_color_tubes(&initial_tubes, height),
+            initial_tubes,
+            table: FxHashMap::default(),
+            table_capacity: DEFAULT_TABLE_CAPACITY,
+            path: FxHashSet::default(),
+            stack: vec![],
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        let initial_tubes = self.initial_tubes.clone();
+        let mut bound = get_lower_bound(&initial_tubes, self.height, &self.color_tubes);
+        loop {
+            self.table.clear();
+            self.path.clear();
+            self.stack.clear();
+            match self.inner_search(&initial_tubes, 0, bound, usize::MAX, usize::MAX) {
+                Ok(Subtree::Solved) => return SearchOutcome::Solved,
+                Ok(Subtree::Exhausted) => return SearchOutcome::Unsolvable,
+                Ok(Subtree::Exceeded(next_bound)) => bound = next_bound,
+                Err(outcome) => return outcome,
+            }
+        }
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let moves = get_moves_from_transforms(
+            self.tubes,
+            self.stack
+                .iter()
+                .map(|state| (state.from, state.to, state.transform.as_slice())),
+        );
+        replay_moves(&self.initial_tubes, self.height, &moves, RuleSet::PourRun)
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> IDAStarSolver<C> {
+    /// Limits the transposition table to `capacity` states, `0` disables it entirely.
+    ///
+    /// States left out of the table are only kept from repeating along the current path, so a
+    /// small table makes the search much slower, but it still ends.
+    pub fn set_table_capacity(&mut self, capacity: usize) {
+        self.table_capacity = capacity;
+    }
+
+    /// Looks for any solution of at most `max_moves` moves, which need not be the shortest one.
+    ///
+    /// Returns [`SearchOutcome::Unsolvable`] when there is no such solution.
+    pub fn search_within(&mut self, max_moves: usize) -> SearchOutcome {
+        self.control.start();
+        self.table.clear();
+        self.path.clear();
+        self.stack.clear();
+        let initial_tubes = self.initial_tubes.clone();
+        match self.inner_search(&initial_tubes, 0, max_moves, usize::MAX, usize::MAX) {
+            Ok(Subtree::Solved) => SearchOutcome::Solved,
+            Ok(Subtree::Exhausted | Subtree::Exceeded(_)) => SearchOutcome::Unsolvable,
+            Err(outcome) => outcome,
+        }
+    }
+
+    /// Searches the subtree of `state` within `bound`, failing only when the budget stops the
+    /// search.
+    fn inner_search(
+        &mut self,
+        state: &[C],
+        depth: usize,
+        bound: usize,
+        from: usize,
+        to: usize,
+    ) -> Result<Subtree, SearchOutcome> {
+        let (transform, sorted_tubes) = get_transform(state, self.height, self.tubes);
+        let estimate = depth + get_lower_bound(&sorted_tubes, self.height, &self.color_tubes);
+        if estimate > bound {
+            return Ok(Subtree::Exceeded(estimate));
+        }
+        let recorded = if let Some(&visited_depth) = self.table.get(&sorted_tubes) {
+            if visited_depth <= depth {
+                self.control.record_duplicates(1);
+                return Ok(Subtree::Exhausted);
+            }
+            self.table.insert(sorted_tubes.clone(), depth);
+            true
+        } else if self.table.len() < self.table_capacity {
+            self.table.insert(sorted_tubes.clone(), depth);
+            true
+        } else {
+            false
+        };
+        if !recorded && !self.path.insert(sorted_tubes.clone()) {
+            self.control.record_duplicates(1);
+            return Ok(Subtree::Exhausted);
+        }
+        self.stack.push(State {
+            from,
+            to,
+            transform,
+        });
+        self.control.record_stack_depth(self.stack.len());
+        if is_solved(&sorted_tubes, self.height) {
+            return Ok(Subtree::Solved);
+        }
+        self.control.expand(1, self.table.len())?;
+        let tube_stats: Vec<TubeStats> = sorted_tubes
+            .chunks_exact(self.height)
+            .map(|tube| get_tube_stat(tube, self.height))
+            .collect();
+        let mut next_bound: Option<usize> = None;
+        for (x, y, amount) in get_legal_moves(&tube_stats, self.height) {
+            let mut tubes = sorted_tubes.clone();
+            pour(&mut tubes, self.height, &tube_stats, x, y, amount);
+            match self.inner_search(&tubes, depth + 1, bound, x, y)? {
+                Subtree::Solved => return Ok(Subtree::Solved),
+                Subtree::Exhausted => {}
+                Subtree::Exceeded(estimate) => {
+                    next_bound = Some(next_bound.map_or(estimate, |bound| bound.min(estimate)));
+                }
+            }
+        }
+        self.stack.pop();
+        if !recorded {
+            self.path.remove(&sorted_tubes);
+        }
+        Ok(next_bound.map_or(Subtree::Exhausted, Subtree::Exceeded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsolvable_with_small_table() {
+        let tubes = parse_tubes(&["a c a d", "b b a c", "b c d a", "d d c b", ""], 4);
+        for capacity in [0, 1, 10, DEFAULT_TABLE_CAPACITY] {
+            let mut solver = IDAStarSolver::new(4, tubes.clone());
+            solver.set_table_capacity(capacity);
+            assert_eq!(solver.search(), SearchOutcome::Unsolvable);
+        }
+    }
+}
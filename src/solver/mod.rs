@@ -1,17 +1,144 @@
+mod anytime_solver;
+mod astar_solver;
+mod beam_solver;
 mod bfs_solver;
+mod bidirectional_solver;
+mod cell;
+mod constraint;
 mod dfs_solver;
+mod extra_tube_solver;
+mod game_state;
+mod hint;
+mod idastar_solver;
+mod mystery;
+mod optimal;
+mod packed_state;
+mod parallel_bfs_solver;
+mod polish;
+mod position;
+mod puzzle;
+mod restart_dfs_solver;
+mod rules;
+mod search;
+mod simulate;
 mod utils;
 
+use puzzle::validate_puzzle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SolutionStep {
     pub from: usize,
     pub to: usize,
+    /// Number of cells poured.
+    pub amount: usize,
+    pub color: usize,
 }
 
-pub trait Solver {
-    fn new(height: usize, initial_tubes: Vec<u8>) -> Self;
-    fn search(&mut self) -> bool;
+pub trait Solver<C: Cell = u8> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self;
+    /// Like [`Solver::new`], but rejects malformed or impossible puzzles.
+    fn try_new(height: usize, initial_tubes: Vec<C>) -> Result<Self, PuzzleError>
+    where
+        Self: Sized,
+    {
+        validate_puzzle(height, &initial_tubes)?;
+        Ok(Self::new(height, initial_tubes))
+    }
+    fn from_state(state: &GameState<C>) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new(state.height(), state.cells().to_vec())
+    }
+    fn set_config(&mut self, config: SearchConfig);
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>);
+    fn search(&mut self) -> SearchOutcome;
     fn get_solution(&self) -> Vec<SolutionStep>;
+    fn get_stats(&self) -> &SearchStats;
 }
 
+pub use anytime_solver::AnytimeSolver;
+pub use astar_solver::AStarSolver;
+pub use beam_solver::{BeamSolver, BeamWeights, DEFAULT_BEAM_WIDTH};
 pub use bfs_solver::BFSSolver;
+pub use bidirectional_solver::BidirectionalSolver;
+pub use cell::Cell;
+pub use constraint::TubeConstraint;
 pub use dfs_solver::DFSSolver;
+pub use extra_tube_solver::{DEFAULT_MAX_EXTRA_TUBES, ExtraTubeSolver};
+pub use game_state::{GameState, Move, MoveError};
+pub use hint::{Hint, get_hint};
+pub use idastar_solver::{DEFAULT_TABLE_CAPACITY, IDAStarSolver};
+pub use mystery::{DEFAULT_MYSTERY_SAMPLES, MysterySession, PlannedMove, RevealError};
+pub use optimal::{OptimalSolutions, count_optimal_solutions};
+pub use parallel_bfs_solver::ParallelBFSSolver;
+pub use polish::{DEFAULT_POLISH_WINDOW, polish_solution};
+pub use position::{MoveAnalysis, MoveClass, PositionAnalysis, analyze_position};
+pub use puzzle::PuzzleError;
+pub use restart_dfs_solver::{DEFAULT_MAX_RESTARTS, DEFAULT_RESTART_BASE, RestartDFSSolver};
+pub use rules::RuleSet;
+pub use search::{
+    BudgetReason, CancellationToken, SearchConfig, SearchObserver, SearchOutcome, SearchStats,
+};
+pub use simulate::{
+    IllegalMove, Simulation, get_solution_states, simulate, simulate_from, simulate_moves,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::utils::parse_tubes;
+
+    /// Solves the puzzle with every optimal solver, checking each solution with [`simulate`], and
+    /// returns the length they agree on.
+    fn optimal_length(height: usize, tubes: &[&str]) -> Option<usize> {
+        fn solve<S: Solver>(mut solver: S, height: usize, tubes: &[u8]) -> Option<usize> {
+            match solver.search() {
+                SearchOutcome::Solved => {}
+                SearchOutcome::Unsolvable => return None,
+                outcome => panic!("unexpected outcome {outcome:?}"),
+            }
+            let steps = solver.get_solution();
+            assert!(simulate(height, tubes, &steps).unwrap().solved);
+            Some(steps.len())
+        }
+
+        let tubes = parse_tubes(tubes, height);
+        let mut parallel = ParallelBFSSolver::new(height, tubes.clone());
+        parallel.set_threads(2);
+        let lengths = [
+            solve(BFSSolver::new(height, tubes.clone()), height, &tubes),
+            solve(AStarSolver::new(height, tubes.clone()), height, &tubes),
+            solve(IDAStarSolver::new(height, tubes.clone()), height, &tubes),
+            solve(
+                BidirectionalSolver::new(height, tubes.clone()),
+                height,
+                &tubes,
+            ),
+            solve(parallel, height, &tubes),
+        ];
+        assert!(
+            lengths.iter().all(|&length| length == lengths[0]),
+            "{lengths:?}"
+        );
+        lengths[0]
+    }
+
+    #[test]
+    fn optimal_solvers_agree() {
+        assert_eq!(optimal_length(2, &["a b", "b a", ""]), Some(3));
+        assert_eq!(
+            optimal_length(3, &["a b c", "c a b", "b c a", "", ""]),
+            Some(7)
+        );
+        let tubes = ["a c a d", "b b a c", "b c d a", "d d c b", "", ""];
+        assert_eq!(optimal_length(4, &tubes), Some(12));
+    }
+
+    #[test]
+    fn optimal_solvers_agree_on_unsolvable() {
+        assert_eq!(optimal_length(2, &["a b", "b a"]), None);
+        let tubes = ["a c a d", "b b a c", "b c d a", "d d c b", ""];
+        assert_eq!(optimal_length(4, &tubes), None);
+    }
+}
@@ -0,0 +1,372 @@
+use std::fmt;
+
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+use super::cell::Cell;
+use super::dfs_solver::DFSSolver;
+use super::game_state::{GameState, Move, MoveError};
+use super::polish::{DEFAULT_POLISH_WINDOW, polish_solution};
+use super::puzzle::{PuzzleError, validate_puzzle};
+use super::{SearchConfig, SearchOutcome, Solver};
+
+/// Number of hidden color arrangements a [`MysterySession`] plans against.
+pub const DEFAULT_MYSTERY_SAMPLES: usize = 32;
+
+/// Why a revealed color was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevealError {
+    InvalidTube,
+    InvalidColor,
+    /// The top cell of the tube is already known, or the tube is empty.
+    NotHidden,
+    /// Every cell of the color is already known.
+    ColorExhausted,
+}
+
+impl fmt::Display for RevealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevealError::InvalidTube => write!(f, "tube does not exist"),
+            RevealError::InvalidColor => write!(f, "color does not exist"),
+            RevealError::NotHidden => write!(f, "top cell of the tube is not hidden"),
+            RevealError::ColorExhausted => write!(f, "every cell of the color is already known"),
+        }
+    }
+}
+
+/// A move suggested by [`MysterySession::plan`], with how it fared against the hidden colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlannedMove {
+    pub step: Move,
+    /// Number of hidden color arrangements the move was tried against.
+    pub arrangements: usize,
+    /// Whether those arrangements were picked at random, as there were too many to try them all.
+    pub sampled: bool,
+    /// Number of those arrangements left unsolvable by the move.
+    pub losing: usize,
+    /// Longest solution, counting the move, over the arrangements it does not lose.
+    pub worst_case: Option<usize>,
+}
+
+impl PlannedMove {
+    /// Whether the puzzle stays solvable after the move in every arrangement it was tried against.
+    ///
+    /// Unless the arrangements were `sampled`, this holds whatever the hidden colors are.
+    pub fn is_safe(&self) -> bool {
+        self.losing == 0
+    }
+}
+
+/// A puzzle whose cells are hidden until they reach the top of their tube.
+///
+/// The session is told which moves are played and which colors they reveal, and suggests the
+/// next move with [`MysterySession::plan`]. Every color has `height` cells, so the colors still
+/// hidden are known as a whole, and moves are judged against arrangements of them: every one when
+/// there are at most [`MysterySession::set_samples`] of them, random ones otherwise. Each
+/// arrangement is solved knowing all of its cells, so a safe move keeps every arrangement it was
+/// tried against solvable, but the player may still need luck to find the solution as cells are
+/// revealed. Only when every arrangement was tried does that cover all the hidden colors.
+pub struct MysterySession<C: Cell = u8> {
+    height: usize,
+    colors: usize,
+    /// The cells, with hidden ones holding the color `colors + 1`.
+    cells: Vec<C>,
+    samples: usize,
+    seed: u64,
+    config: SearchConfig,
+}
+
+impl<C: Cell> MysterySession<C> {
+    /// Starts a session on `cells`, listed tube by tube from the bottom, where `None` is a hidden
+    /// cell and colors are numbered from 1 to `colors`.
+    ///
+    /// `colors` must be below [`Cell::MAX_COLOR`], as hidden cells are stored as one more color.
+    pub fn new(height: usize, colors: usize, cells: Vec<Option<C>>) -> Result<Self, PuzzleError> {
+        if height == 0 {
+            return Err(PuzzleError::ZeroHeight);
+        }
+        if colors >= C::MAX_COLOR {
+            return Err(PuzzleError::TooManyColors {
+                colors,
+                tubes: cells.len() / height,
+            });
+        }
+        if let Some(color) = cells
+            .iter()
+            .flatten()
+            .map(|cell| cell.color())
+            .find(|&color| color > colors)
+        {
+            return Err(PuzzleError::ColorOutOfRange { color, colors });
+        }
+        let hidden = C::from_color(colors + 1);
+        let session = Self {
+            height,
+            colors,
+            cells: cells
+                .into_iter()
+                .map(|cell| cell.unwrap_or(hidden))
+                .collect(),
+            samples: DEFAULT_MYSTERY_SAMPLES,
+            seed: 0,
+            config: SearchConfig::default(),
+        };
+        let missing = session.missing_colors()?;
+        let hidden_cells = session.cells.iter().filter(|&&cell| cell == hidden).count();
+        if missing.len() != hidden_cells {
+            return Err(PuzzleError::HiddenCountMismatch {
+                hidden: hidden_cells,
+                missing: missing.len(),
+            });
+        }
+        validate_puzzle(height, &session.arrange(&missing))?;
+        Ok(session)
+    }
+
+    /// Sets the number of hidden color arrangements each plan is judged against, defaulting to
+    /// [`DEFAULT_MYSTERY_SAMPLES`].
+    pub fn set_samples(&mut self, samples: usize) {
+        self.samples = samples.max(1);
+    }
+
+    /// Sets the seed used to pick arrangements when there are too many to try them all.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Limits each search solving an arrangement. A search running out of budget counts as a
+    /// loss for the move.
+    pub fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    /// The known cells, with `None` for the hidden ones.
+    pub fn cells(&self) -> Vec<Option<C>> {
+        let hidden = self.hidden();
+        self.cells
+            .iter()
+            .map(|&cell| (cell != hidden).then_some(cell))
+            .collect()
+    }
+
+    pub fn is_solved(&self) -> bool {
+        !self.cells.contains(&self.hidden())
+            && GameState::new(self.height, self.cells.clone()).is_solved()
+    }
+
+    /// Plays a move, which pours the known top cells of the source.
+    ///
+    /// A hidden cell left on top of the source should then be given with
+    /// [`MysterySession::reveal`]. If the game also poured it because it had the same color, play
+    /// the same move again once it is revealed.
+    pub fn play(&mut self, mv: Move) -> Result<(), MoveError> {
+        let mut game = GameState::new(self.height, self.cells.clone());
+        if game.top_color(mv.from) == Some(self.hidden()) {
+            return Err(MoveError::HiddenSource);
+        }
+        game.apply(mv)?;
+        self.cells = game.cells().to_vec();
+        Ok(())
+    }
+
+    /// Records the color of the hidden cell on top of `tube`.
+    pub fn reveal(&mut self, tube: usize, color: C) -> Result<(), RevealError> {
+        if tube >= self.cells.len() / self.height {
+            return Err(RevealError::InvalidTube);
+        }
+        let game = GameState::new(self.height, self.cells.clone());
+        if game.top_color(tube) != Some(self.hidden()) {
+            return Err(RevealError::NotHidden);
+        }
+        if color == C::EMPTY || color.color() > self.colors {
+            return Err(RevealError::InvalidColor);
+        }
+        if self.cells.iter().filter(|&&cell| cell == color).count() >= self.height {
+            return Err(RevealError::ColorExhausted);
+        }
+        self.cells[tube * self.height + game.size(tube) - 1] = color;
+        Ok(())
+    }
+
+    /// Suggests the next move, or `None` when the puzzle is solved or no move can be played.
+    ///
+    /// Safe moves come first, then moves losing the fewest arrangements, each ordered by their
+    /// worst case solution length.
+    pub fn plan(&self) -> Option<PlannedMove> {
+        if self.is_solved() {
+            return None;
+        }
+        let missing = self.missing_colors().unwrap();
+        let sampled = count_arrangements(&missing, self.samples).is_none();
+        let arrangements = self.arrangements(&missing, sampled);
+        let game = GameState::new(self.height, self.cells.clone());
+        let hidden = self.hidden();
+        game.legal_moves()
+            .into_iter()
+            .filter(|&mv| game.top_color(mv.from) != Some(hidden))
+            .map(|step| {
+                let mut planned = PlannedMove {
+                    step,
+                    arrangements: arrangements.len(),
+                    sampled,
+                    losing: 0,
+                    worst_case: None,
+                };
+                for cells in arrangements.iter() {
+                    match self.solve_after(cells, step) {
+                        Some(moves) => {
+                            planned.worst_case = planned.worst_case.max(Some(moves + 1));
+                        }
+                        None => planned.losing += 1,
+                    }
+                }
+                planned
+            })
+            .min_by_key(|planned| {
+                (
+                    planned.losing,
+                    planned.worst_case.unwrap_or(usize::MAX),
+                    planned.step.from,
+                    planned.step.to,
+                )
+            })
+    }
+
+    fn hidden(&self) -> C {
+        C::from_color(self.colors + 1)
+    }
+
+    /// The colors of the hidden cells, in ascending order.
+    fn missing_colors(&self) -> Result<Vec<C>, PuzzleError> {
+        let mut counts = vec![0; self.colors + 2];
+        for &cell in self.cells.iter() {
+            counts[cell.color()] += 1;
+        }
+        let mut missing = vec![];
+        for (color, &count) in counts.iter().enumerate().take(self.colors + 1).skip(1) {
+            if count > self.height {
+                return Err(PuzzleError::CellCountMismatch {
+                    color,
+                    count,
+                    height: self.height,
+                });
+            }
+            missing.extend(std::iter::repeat_n(
+                C::from_color(color),
+                self.height - count,
+            ));
+        }
+        Ok(missing)
+    }
+
+    /// Fills the hidden cells with `colors`, in order.
+    fn arrange(&self, colors: &[C]) -> Vec<C> {
+        let hidden = self.hidden();
+        let mut colors = colors.iter();
+        self.cells
+            .iter()
+            .map(|&cell| {
+                if cell == hidden {
+                    *colors.next().unwrap()
+                } else {
+                    cell
+                }
+            })
+            .collect()
+    }
+
+    /// Every arrangement of the `missing` colors, or random ones when `sampled`.
+    fn arrangements(&self, missing: &[C], sampled: bool) -> Vec<Vec<C>> {
+        let mut colors = missing.to_vec();
+        if !sampled {
+            let mut arrangements = vec![];
+            loop {
+                arrangements.push(self.arrange(&colors));
+                if !next_permutation(&mut colors) {
+                    return arrangements;
+                }
+            }
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        (0..self.samples)
+            .map(|_| {
+                colors.shuffle(&mut rng);
+                self.arrange(&colors)
+            })
+            .collect()
+    }
+
+    /// Length of a solution of `cells` after `step`, or `None` when none is found.
+    fn solve_after(&self, cells: &[C], step: Move) -> Option<usize> {
+        let mut game = GameState::new(self.height, cells.to_vec());
+        game.apply(step).unwrap();
+        let mut solver = DFSSolver::new(self.height, game.cells().to_vec());
+        solver.set_config(self.config.clone());
+        if solver.search() != SearchOutcome::Solved {
+            return None;
+        }
+        let solution = solver.get_solution();
+        Some(polish_solution(self.height, game.cells(), &solution, DEFAULT_POLISH_WINDOW).len())
+    }
+}
+
+/// Number of distinct orderings of `colors`, which must be sorted, or `None` when it exceeds
+/// `limit`.
+fn count_arrangements<C: Cell>(colors: &[C], limit: usize) -> Option<usize> {
+    // Builds the multinomial coefficient one cell at a time: the `n`-th cell, the `k`-th of its
+    // color, multiplies the count by `n / k`.
+    let mut count: u128 = 1;
+    let mut run = 0;
+    for (index, &color) in colors.iter().enumerate() {
+        run = if index > 0 && colors[index - 1] == color {
+            run + 1
+        } else {
+            1
+        };
+        count = count * (index as u128 + 1) / run;
+        if count > limit as u128 {
+            return None;
+        }
+    }
+    Some(count as usize)
+}
+
+/// Rearranges `items` into the next ordering in lexicographic order, returning `false` once they
+/// are back to the first one.
+fn next_permutation<T: Ord>(items: &mut [T]) -> bool {
+    let Some(pivot) = items.windows(2).rposition(|pair| pair[0] < pair[1]) else {
+        items.reverse();
+        return false;
+    };
+    let successor = items.iter().rposition(|item| *item > items[pivot]).unwrap();
+    items.swap(pivot, successor);
+    items[pivot + 1..].reverse();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampled_plans_are_marked() {
+        // Six hidden cells of three colors have 90 arrangements.
+        let mut cells = vec![Some(0u8); 15];
+        for tube in 0..3 {
+            cells[tube * 3..tube * 3 + 2].fill(None);
+            cells[tube * 3 + 2] = Some(tube as u8 + 1);
+        }
+        let mut session = MysterySession::new(3, 3, cells).unwrap();
+        session.set_samples(8);
+        let planned = session.plan().unwrap();
+        assert!(planned.sampled);
+        assert_eq!(planned.arrangements, 8);
+        assert_eq!(session.plan(), Some(planned));
+        session.set_samples(90);
+        let planned = session.plan().unwrap();
+        assert!(!planned.sampled);
+        assert_eq!(planned.arrangements, 90);
+    }
+}
@@ -0,0 +1,151 @@
+use rustc_hash::FxHashMap;
+
+use super::astar_solver::AStarSolver;
+use super::cell::Cell;
+use super::game_state::{GameState, Move};
+use super::packed_state::{PackedState, StatePacker};
+use super::utils::*;
+use super::{SolutionStep, Solver};
+
+/// Every minimal-length solution of a puzzle.
+///
+/// Solutions are counted up to the order of identical tubes: pours whose source and target hold
+/// the same cells as those of an earlier pour of the same state are left out, so pouring into
+/// either of two empty tubes counts once.
+pub struct OptimalSolutions {
+    pub moves: usize,
+    pub count: u128,
+    pub solutions: Vec<Vec<SolutionStep>>,
+}
+
+struct SolutionCounter {
+    height: usize,
+    tubes: usize,
+    color_tubes: Vec<usize>,
+    packer: StatePacker,
+    counts: FxHashMap<(PackedState, usize), u128>,
+}
+
+impl SolutionCounter {
+    /// Number of pour sequences of exactly `remaining` moves solving `state`.
+    ///
+    /// This only depends on the sorted state, since reordering the tubes maps pour sequences one
+    /// to one, so it is memoized on the sorted state.
+    fn count<C: Cell>(&mut self, state: &[C], remaining: usize) -> u128 {
+        let (_, sorted_tubes) = get_transform(state, self.height, self.tubes);
+        if is_solved(&sorted_tubes, self.height) {
+            return (remaining == 0) as u128;
+        }
+        if get_lower_bound(&sorted_tubes, self.height, &self.color_tubes) > remaining {
+            return 0;
+        }
+        let key = (self.packer.pack(&sorted_tubes), remaining);
+        if let Some(&count) = self.counts.get(&key) {
+            return count;
+        }
+        let mut count: u128 = 0;
+        let mut game = GameState::new(self.height, sorted_tubes);
+        for mv in distinct_moves(&game) {
+            game.apply(mv).unwrap();
+            count = count.saturating_add(self.count(game.cells(), remaining - 1));
+            game.undo();
+        }
+        self.counts.insert(key, count);
+        count
+    }
+
+    fn list<C: Cell>(
+        &mut self,
+        game: &mut GameState<C>,
+        remaining: usize,
+        limit: usize,
+        steps: &mut Vec<SolutionStep>,
+        solutions: &mut Vec<Vec<SolutionStep>>,
+    ) {
+        if solutions.len() >= limit {
+            return;
+        }
+        if remaining == 0 {
+            solutions.push(steps.clone());
+            return;
+        }
+        for mv in distinct_moves(game) {
+            if solutions.len() >= limit {
+                break;
+            }
+            game.apply(mv).unwrap();
+            if self.count(game.cells(), remaining - 1) > 0 {
+                steps.push(*game.history().last().unwrap());
+                self.list(game, remaining - 1, limit, steps, solutions);
+                steps.pop();
+            }
+            game.undo();
+        }
+    }
+}
+
+/// The legal moves of `game`, leaving out those pouring between tubes identical to the tubes of
+/// an earlier move.
+fn distinct_moves<C: Cell>(game: &GameState<C>) -> Vec<Move> {
+    let mut seen: Vec<(&[C], &[C])> = vec![];
+    let mut moves = vec![];
+    for mv in game.legal_moves() {
+        let tubes = (game.tube(mv.from), game.tube(mv.to));
+        if !seen.contains(&tubes) {
+            seen.push(tubes);
+            moves.push(mv);
+        }
+    }
+    moves
+}
+
+/// Counts the optimal solutions of a puzzle and lists up to `limit` of them, or returns `None`
+/// if the puzzle has no solution.
+pub fn count_optimal_solutions<C: Cell>(
+    height: usize,
+    initial_tubes: Vec<C>,
+    limit: usize,
+) -> Option<OptimalSolutions> {
+    let mut solver = AStarSolver::new(height, initial_tubes.clone());
+    if !solver.search().is_solved() {
+        return None;
+    }
+    let moves = solver.get_solution().len();
+    let mut counter = SolutionCounter {
+        height,
+        tubes: initial_tubes.len() / height,
+        color_tubes: get_color_tubes(&initial_tubes, height),
+        packer: StatePacker::new(&initial_tubes),
+        counts: FxHashMap::default(),
+    };
+    let count = counter.count(&initial_tubes, moves);
+    let mut solutions = vec![];
+    let mut game = GameState::new(height, initial_tubes);
+    counter.list(&mut game, moves, limit, &mut vec![], &mut solutions);
+    Some(OptimalSolutions {
+        moves,
+        count,
+        solutions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_tubes_count_once() {
+        let optimal = count_optimal_solutions(2, parse_tubes(&["a", "a", ""], 2), 10).unwrap();
+        assert_eq!(optimal.moves, 1);
+        assert_eq!(optimal.count, 1);
+        assert_eq!(optimal.solutions.len(), 1);
+
+        // Either top cell goes into one of the empty tubes, then the other color fills up its tube
+        // and the two identical single-cell tubes are merged.
+        let tubes = parse_tubes(&["a b", "b a", "", ""], 2);
+        let optimal = count_optimal_solutions(2, tubes, 10).unwrap();
+        assert_eq!(optimal.moves, 3);
+        assert_eq!(optimal.count, 2);
+        assert_eq!(optimal.solutions.len(), 2);
+    }
+}
@@ -0,0 +1,111 @@
+use super::cell::Cell;
+
+const INLINE_WORDS: usize = 4;
+
+/// Tube contents packed into as few bits per cell as the color count allows.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(super) enum PackedState {
+    Inline([u64; INLINE_WORDS]),
+    Heap(Box<[u64]>),
+}
+
+impl PackedState {
+    fn words(&self) -> &[u64] {
+        match self {
+            PackedState::Inline(words) => words,
+            PackedState::Heap(words) => words,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct StatePacker {
+    bits: usize,
+    cells_per_word: usize,
+    words: usize,
+}
+
+impl StatePacker {
+    pub(super) fn new<C: Cell>(tubes: &[C]) -> Self {
+        let colors = tubes.iter().copied().max().map_or(0, C::color);
+        let bits = (usize::BITS - colors.leading_zeros()).max(1) as usize;
+        let cells_per_word = u64::BITS as usize / bits;
+        Self {
+            bits,
+            cells_per_word,
+            words: tubes.len().div_ceil(cells_per_word),
+        }
+    }
+
+    pub(super) fn pack<C: Cell>(&self, tubes: &[C]) -> PackedState {
+        let mut state = if self.words <= INLINE_WORDS {
+            PackedState::Inline([0; INLINE_WORDS])
+        } else {
+            PackedState::Heap(vec![0; self.words].into_boxed_slice())
+        };
+        let words = match &mut state {
+            PackedState::Inline(words) => &mut words[..],
+            PackedState::Heap(words) => &mut words[..],
+        };
+        for (word, cells) in words.iter_mut().zip(tubes.chunks(self.cells_per_word)) {
+            for (index, &cell) in cells.iter().enumerate() {
+                *word |= (cell.color() as u64) << (index * self.bits);
+            }
+        }
+        state
+    }
+
+    pub(super) fn unpack<C: Cell>(&self, state: &PackedState, tubes: &mut [C]) {
+        let mask = (1 << self.bits) - 1;
+        for (word, cells) in state
+            .words()
+            .iter()
+            .zip(tubes.chunks_mut(self.cells_per_word))
+        {
+            for (index, cell) in cells.iter_mut().enumerate() {
+                *cell = C::from_color(((word >> (index * self.bits)) & mask) as usize);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<C: Cell>(tubes: &[C]) -> PackedState {
+        let packer = StatePacker::new(tubes);
+        let state = packer.pack(tubes);
+        let mut unpacked = vec![C::EMPTY; tubes.len()];
+        packer.unpack(&state, &mut unpacked);
+        assert_eq!(unpacked, tubes);
+        state
+    }
+
+    #[test]
+    fn inline_round_trip() {
+        let tubes: Vec<u8> = vec![1, 2, 1, 0, 2, 1, 2, 0, 0, 0, 0, 0];
+        assert!(matches!(round_trip(&tubes), PackedState::Inline(_)));
+        let mut swapped = tubes.clone();
+        swapped.swap(0, 1);
+        let packer = StatePacker::new(&tubes);
+        assert!(packer.pack(&tubes) != packer.pack(&swapped));
+    }
+
+    #[test]
+    fn heap_round_trip() {
+        // 255 colors take 8 bits per cell, so 4 words hold only 32 cells.
+        let tubes: Vec<u8> = (0..=255).chain(0..=255).map(|color| color as u8).collect();
+        assert!(matches!(round_trip(&tubes), PackedState::Heap(_)));
+    }
+
+    #[test]
+    fn wide_cells_round_trip() {
+        let tubes: Vec<u16> = (0..1000).map(|index| (index * 7 % 600) as u16).collect();
+        let packer = StatePacker::new(&tubes);
+        assert_eq!(packer.bits, 10);
+        assert!(matches!(round_trip(&tubes), PackedState::Heap(_)));
+        let tubes: Vec<u16> = vec![300, 300, 1, 0];
+        assert!(matches!(round_trip(&tubes), PackedState::Inline(_)));
+    }
+}
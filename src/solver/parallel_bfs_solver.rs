@@ -0,0 +1,239 @@
+use std::hash::BuildHasher;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::thread;
+
+use rustc_hash::{FxBuildHasher, FxHashMap};
+
+use super::cell::Cell;
+use super::game_state::GameState;
+use super::rules::RuleSet;
+use super::search::{Interrupt, SearchControl};
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+const SHARDS_PER_THREAD: usize = 8;
+
+/// Number of expansions a worker performs between two interrupt checks.
+const INTERRUPT_INTERVAL: usize = 256;
+
+struct Node {
+    parent: usize,
+    from: usize,
+    to: usize,
+    amount: usize,
+}
+
+/// A child discovered in the current layer, ordered by `(parent, move)` so that the layer comes
+/// out in the same order as in the sequential [`super::BFSSolver`].
+struct Candidate {
+    order: (usize, usize),
+    from: usize,
+    to: usize,
+    amount: usize,
+}
+
+#[derive(Default)]
+struct Shard<C> {
+    visited: FxHashMap<Vec<C>, usize>,
+    layer: FxHashMap<Vec<C>, Candidate>,
+}
+
+pub struct ParallelBFSSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    threads: usize,
+    nodes: Vec<Node>,
+    goal: Option<usize>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for ParallelBFSSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            initial_tubes,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            nodes: vec![],
+            goal: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        let shards: Vec<Mutex<Shard<C>>> = (0..self.threads * SHARDS_PER_THREAD)
+            .map(|_| Mutex::new(Shard::default()))
+            .collect();
+        let (_, sorted_tubes) = get_transform(&self.initial_tubes, self.height, self.tubes);
+        self.nodes.push(Node {
+            parent: usize::MAX,
+            from: usize::MAX,
+            to: usize::MAX,
+            amount: 0,
+        });
+        if is_solved(&sorted_tubes, self.height) {
+            self.goal = Some(0);
+            return SearchOutcome::Solved;
+        }
+        shards[get_shard_index(&sorted_tubes, shards.len())]
+            .lock()
+            .unwrap()
+            .visited
+            .insert(sorted_tubes.clone(), 0);
+        self.control.record_frontier(0, 1);
+        let mut layer = vec![(0, sorted_tubes)];
+        let mut depth = 0;
+        while !layer.is_empty() {
+            if let Err(outcome) = self.control.expand(layer.len(), self.nodes.len()) {
+                return outcome;
+            }
+            let chunk_size = layer.len().div_ceil(self.threads);
+            let interrupt = self.control.interrupt();
+            let generated: usize = thread::scope(|scope| {
+                let mut handles = vec![];
+                for (chunk_index, chunk) in layer.chunks(chunk_size).enumerate() {
+                    let worker = Worker {
+                        height: self.height,
+                        tubes: self.tubes,
+                        interrupt: &interrupt,
+                        shards: &shards,
+                    };
+                    handles.push(
+                        scope.spawn(move || worker.expand_chunk(chunk, chunk_index * chunk_size)),
+                    );
+                }
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .sum()
+            });
+            if let Err(outcome) = interrupt.check() {
+                return outcome;
+            }
+
+            let mut candidates: Vec<(Vec<C>, Candidate)> = shards
+                .iter()
+                .flat_map(|shard| shard.lock().unwrap().layer.drain().collect::<Vec<_>>())
+                .collect();
+            candidates.sort_unstable_by_key(|(_, candidate)| candidate.order);
+            self.control.record_duplicates(generated - candidates.len());
+            depth += 1;
+            self.control.record_frontier(depth, candidates.len());
+            let mut next_layer = Vec::with_capacity(candidates.len());
+            for (tubes, candidate) in candidates {
+                let index = self.nodes.len();
+                self.nodes.push(Node {
+                    parent: layer[candidate.order.0].0,
+                    from: candidate.from,
+                    to: candidate.to,
+                    amount: candidate.amount,
+                });
+                if is_solved(&tubes, self.height) {
+                    self.goal = Some(index);
+                    return SearchOutcome::Solved;
+                }
+                shards[get_shard_index(&tubes, shards.len())]
+                    .lock()
+                    .unwrap()
+                    .visited
+                    .insert(tubes.clone(), index);
+                next_layer.push((index, tubes));
+            }
+            layer = next_layer;
+        }
+        SearchOutcome::Unsolvable
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        let mut moves = vec![];
+        let mut index = self.goal.unwrap();
+        while index > 0 {
+            let node = &self.nodes[index];
+            moves.push((node.from, node.to, node.amount));
+            index = node.parent;
+        }
+        moves.reverse();
+        get_steps_from_moves(
+            GameState::new(self.height, self.initial_tubes.clone()),
+            &moves,
+        )
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> ParallelBFSSolver<C> {
+    /// Sets the number of worker threads, defaulting to the available parallelism.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+}
+
+/// The shared context of the threads expanding one layer.
+#[derive(Clone, Copy)]
+struct Worker<'a, C> {
+    height: usize,
+    tubes: usize,
+    interrupt: &'a Interrupt,
+    shards: &'a [Mutex<Shard<C>>],
+}
+
+impl<C: Cell> Worker<'_, C> {
+    /// Returns the number of generated children.
+    fn expand_chunk(&self, chunk: &[(usize, Vec<C>)], offset: usize) -> usize {
+        let mut generated = 0;
+        for (index, (_, state)) in chunk.iter().enumerate() {
+            if index % INTERRUPT_INTERVAL == 0 && self.interrupt.check().is_err() {
+                return generated;
+            }
+            let tube_stats: Vec<TubeStats> = state
+                .chunks_exact(self.height)
+                .map(|tube| get_tube_stat(tube, self.height))
+                .collect();
+            let moves = get_bfs_moves(&tube_stats, self.height, RuleSet::PourRun);
+            for (move_index, (from, to, amount)) in moves.into_iter().enumerate() {
+                generated += 1;
+                let mut tubes = state.clone();
+                pour(&mut tubes, self.height, &tube_stats, from, to, amount);
+                let (_, sorted_tubes) = get_transform(&tubes, self.height, self.tubes);
+                let candidate = Candidate {
+                    order: (offset + index, move_index),
+                    from,
+                    to,
+                    amount,
+                };
+                let mut shard = self.shards[get_shard_index(&sorted_tubes, self.shards.len())]
+                    .lock()
+                    .unwrap();
+                if shard.visited.contains_key(&sorted_tubes) {
+                    continue;
+                }
+                match shard.layer.get_mut(&sorted_tubes) {
+                    Some(existing) if existing.order < candidate.order => {}
+                    Some(existing) => *existing = candidate,
+                    None => {
+                        shard.layer.insert(sorted_tubes, candidate);
+                    }
+                }
+            }
+        }
+        generated
+    }
+}
+
+fn get_shard_index<C: Cell>(tubes: &[C], shards: usize) -> usize {
+    FxBuildHasher.hash_one(tubes) as usize % shards
+}
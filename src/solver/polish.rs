@@ -0,0 +1,184 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::SolutionStep;
+use super::cell::Cell;
+use super::game_state::{GameState, Move};
+use super::utils::*;
+
+/// Number of moves searched around each state of a solution by [`polish_solution`].
+pub const DEFAULT_POLISH_WINDOW: usize = 3;
+
+/// Number of states explored around each state of a solution before giving up on a shortcut.
+const MAX_WINDOW_STATES: usize = 1 << 12;
+
+/// Shortens a valid solution of `tubes`.
+///
+/// Moves that come back to an earlier state are cut out first. Then a breadth-first search of
+/// `window` moves around each state looks for a shorter path to any later state of the solution,
+/// which also merges consecutive pours that could have been done at once. States are compared in
+/// sorted form, so a shortcut may leave the tubes in a different order, and the remaining steps
+/// are mapped back to the actual tubes at the end.
+pub fn polish_solution<C: Cell>(
+    height: usize,
+    tubes: &[C],
+    steps: &[SolutionStep],
+    window: usize,
+) -> Vec<SolutionStep> {
+    let tube_count = tubes.len() / height;
+    let mut game = GameState::new(height, tubes.to_vec());
+    let mut states = vec![get_transform(tubes, height, tube_count).1];
+    for &step in steps {
+        game.apply(Move::from(step)).unwrap();
+        states.push(get_transform(game.cells(), height, tube_count).1);
+    }
+
+    states = remove_cycles(states);
+    let mut i = 0;
+    while i + 1 < states.len() {
+        let positions: FxHashMap<&[C], usize> = states
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (state.as_slice(), index))
+            .collect();
+        let shortcut = (i..states.len() - 1).find_map(|start| {
+            find_shortcut(
+                height,
+                tube_count,
+                &states[start],
+                &positions,
+                start,
+                window,
+            )
+            .map(|(end, path)| (start, end, path))
+        });
+        let Some((start, end, path)) = shortcut else {
+            break;
+        };
+        states.splice(start + 1..=end, path);
+        states = remove_cycles(states);
+        i = start;
+    }
+
+    let states: Vec<&[C]> = states[1..].iter().map(Vec::as_slice).collect();
+    get_steps_between(tubes, height, tube_count, &states)
+}
+
+/// Drops every loop of the solution, keeping the last visit of each state.
+fn remove_cycles<C: Cell>(states: Vec<Vec<C>>) -> Vec<Vec<C>> {
+    let mut positions: FxHashMap<Vec<C>, usize> = FxHashMap::default();
+    let mut result: Vec<Vec<C>> = vec![];
+    for state in states {
+        if let Some(&position) = positions.get(&state) {
+            for removed in result.drain(position + 1..) {
+                positions.remove(&removed);
+            }
+            continue;
+        }
+        positions.insert(state.clone(), result.len());
+        result.push(state);
+    }
+    result
+}
+
+/// Searches `window` moves from the state at `start` for a shorter way to a later state.
+///
+/// Returns the index of that state and the states leading to it, ending with it.
+fn find_shortcut<C: Cell>(
+    height: usize,
+    tube_count: usize,
+    state: &[C],
+    positions: &FxHashMap<&[C], usize>,
+    start: usize,
+    window: usize,
+) -> Option<(usize, Vec<Vec<C>>)> {
+    let mut nodes: Vec<(Vec<C>, usize)> = vec![(state.to_vec(), usize::MAX)];
+    let mut visited: FxHashSet<Vec<C>> = FxHashSet::default();
+    visited.insert(state.to_vec());
+    // (saved moves, node, position)
+    let mut best: Option<(usize, usize, usize)> = None;
+    let mut layer = 0..1;
+    for depth in 1..=window {
+        let end = nodes.len();
+        for parent in layer {
+            if nodes.len() >= MAX_WINDOW_STATES {
+                break;
+            }
+            let tube_stats: Vec<TubeStats> = nodes[parent]
+                .0
+                .chunks_exact(height)
+                .map(|tube| get_tube_stat(tube, height))
+                .collect();
+            for (from, to, amount) in get_legal_moves(&tube_stats, height) {
+                let mut tubes = nodes[parent].0.clone();
+                pour(&mut tubes, height, &tube_stats, from, to, amount);
+                let (_, sorted_tubes) = get_transform(&tubes, height, tube_count);
+                if !visited.insert(sorted_tubes.clone()) {
+                    continue;
+                }
+                if let Some(&position) = positions.get(sorted_tubes.as_slice())
+                    && position > start + depth
+                    && best.is_none_or(|(saved, _, _)| position - start - depth > saved)
+                {
+                    best = Some((position - start - depth, nodes.len(), position));
+                }
+                nodes.push((sorted_tubes, parent));
+            }
+        }
+        layer = end..nodes.len();
+    }
+
+    let (_, mut index, position) = best?;
+    let mut path = vec![];
+    while index > 0 {
+        path.push(nodes[index].0.clone());
+        index = nodes[index].1;
+    }
+    path.reverse();
+    Some((position, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+    use crate::solver::dfs_solver::DFSSolver;
+
+    fn assert_solves(height: usize, tubes: &[u8], steps: &[SolutionStep]) {
+        let mut game = GameState::new(height, tubes.to_vec());
+        for &step in steps {
+            game.apply_step(step).unwrap();
+        }
+        assert!(game.is_solved());
+    }
+
+    #[test]
+    fn polished_dfs_solution() {
+        let tubes = parse_tubes(
+            &[
+                "h b d b", "h a a e", "f c b c", "e g e c", "g g a d", "g d b f", "d f f h",
+                "c a h e", "", "",
+            ],
+            4,
+        );
+        let mut solver = DFSSolver::new(4, tubes.clone());
+        assert!(solver.search().is_solved());
+        let solution = solver.get_solution();
+        let polished = polish_solution(4, &tubes, &solution, DEFAULT_POLISH_WINDOW);
+        assert_solves(4, &tubes, &polished);
+        assert!(polished.len() <= solution.len());
+    }
+
+    #[test]
+    fn cycles_are_cut() {
+        let tubes = parse_tubes(&["a b", "b a", "", ""], 2);
+        let mut game = GameState::new(2, tubes.clone());
+        // Moving the poured run from one empty tube to the other comes back to the same state.
+        for (from, to) in [(0, 2), (2, 3), (1, 0), (1, 3)] {
+            game.apply(Move { from, to }).unwrap();
+        }
+        let solution = game.history().to_vec();
+        let polished = polish_solution(2, &tubes, &solution, DEFAULT_POLISH_WINDOW);
+        assert_solves(2, &tubes, &polished);
+        assert_eq!(polished.len(), 3);
+    }
+}
@@ -0,0 +1,94 @@
+use rustc_hash::FxHashMap;
+
+use super::astar_solver::AStarSolver;
+use super::cell::Cell;
+use super::game_state::{GameState, Move};
+use super::utils::*;
+use super::{SearchConfig, SearchOutcome, Solver};
+
+/// How a move changes the shortest solution of a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveClass {
+    /// The move starts a shortest solution.
+    Optimal,
+    /// The shortest solution after the move is `extra` moves longer.
+    Suboptimal { extra: usize },
+    /// The puzzle can no longer be solved after the move.
+    Losing,
+    /// The search after the move stopped before it could tell.
+    Unknown(SearchOutcome),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveAnalysis {
+    pub step: Move,
+    pub class: MoveClass,
+    /// Length of the shortest solution after the move, when it is solvable and known.
+    pub remaining: Option<usize>,
+}
+
+/// Every legal move of a position, see [`analyze_position`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionAnalysis {
+    /// Length of the shortest solution of the position, or `None` when it is unsolvable.
+    pub optimal_moves: Option<usize>,
+    /// One entry per legal move, ordered by source then target tube.
+    pub moves: Vec<MoveAnalysis>,
+}
+
+/// Classifies every legal move of the position `tubes` by the shortest solution it leaves.
+///
+/// The position and each state reached are solved with [`AStarSolver`] under `config`, and states
+/// equal up to the order of the tubes are only solved once. Fails with the outcome of the search
+/// of the position itself when it stops early.
+pub fn analyze_position<C: Cell>(
+    height: usize,
+    tubes: Vec<C>,
+    config: SearchConfig,
+) -> Result<PositionAnalysis, SearchOutcome> {
+    let mut solved: FxHashMap<Vec<C>, Result<Option<usize>, SearchOutcome>> = FxHashMap::default();
+    let mut solve = |cells: &[C]| {
+        let (_, sorted_tubes) = get_transform(cells, height, cells.len() / height);
+        *solved
+            .entry(sorted_tubes)
+            .or_insert_with_key(|sorted_tubes| {
+                let mut solver = AStarSolver::new(height, sorted_tubes.clone());
+                solver.set_config(config.clone());
+                match solver.search() {
+                    SearchOutcome::Solved => Ok(Some(solver.get_solution().len())),
+                    SearchOutcome::Unsolvable => Ok(None),
+                    outcome => Err(outcome),
+                }
+            })
+    };
+
+    let mut game = GameState::new(height, tubes);
+    let optimal_moves = solve(game.cells())?;
+    let mut moves = vec![];
+    for step in game.legal_moves() {
+        game.apply(step).unwrap();
+        let (class, remaining) = match (solve(game.cells()), optimal_moves) {
+            (Ok(Some(remaining)), Some(optimal)) if remaining + 1 == optimal => {
+                (MoveClass::Optimal, Some(remaining))
+            }
+            (Ok(Some(remaining)), Some(optimal)) => (
+                MoveClass::Suboptimal {
+                    extra: remaining + 1 - optimal,
+                },
+                Some(remaining),
+            ),
+            (Ok(_), _) => (MoveClass::Losing, None),
+            (Err(outcome), _) => (MoveClass::Unknown(outcome), None),
+        };
+        game.undo();
+        moves.push(MoveAnalysis {
+            step,
+            class,
+            remaining,
+        });
+    }
+    Ok(PositionAnalysis {
+        optimal_moves,
+        moves,
+    })
+}
@@ -0,0 +1,308 @@
+use std::error::Error;
+use std::fmt;
+
+use super::cell::Cell;
+
+/// Why a list of cells does not describe a solvable puzzle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleError {
+    ZeroHeight,
+    NoTubes,
+    LengthNotMultipleOfHeight {
+        length: usize,
+        height: usize,
+    },
+    ZeroCapacity {
+        tube: usize,
+    },
+    /// The cells do not match the capacities, which store each tube as many cells apart as the
+    /// largest capacity.
+    LengthMismatch {
+        length: usize,
+        expected: usize,
+    },
+    /// A filled cell above the capacity of its tube.
+    OverCapacity {
+        tube: usize,
+        capacity: usize,
+    },
+    /// An empty cell below a filled one, with cells counted from the bottom.
+    GapBelowFilledCell {
+        tube: usize,
+        cell: usize,
+    },
+    TooManyColors {
+        colors: usize,
+        tubes: usize,
+    },
+    /// A known cell with a color above the number of colors.
+    ColorOutOfRange {
+        color: usize,
+        colors: usize,
+    },
+    /// The hidden cells do not match the cells missing from the known colors.
+    HiddenCountMismatch {
+        hidden: usize,
+        missing: usize,
+    },
+    /// A color without exactly `height` cells, the number that fills one tube.
+    CellCountMismatch {
+        color: usize,
+        count: usize,
+        height: usize,
+    },
+}
+
+impl fmt::Display for PuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PuzzleError::ZeroHeight => write!(f, "tube height must be positive"),
+            PuzzleError::NoTubes => write!(f, "puzzle has no tubes"),
+            PuzzleError::LengthNotMultipleOfHeight { length, height } => {
+                write!(f, "{length} cells do not fill tubes of height {height}")
+            }
+            PuzzleError::ZeroCapacity { tube } => write!(f, "tube {tube} has no capacity"),
+            PuzzleError::LengthMismatch { length, expected } => {
+                write!(f, "{length} cells given, {expected} expected")
+            }
+            PuzzleError::OverCapacity { tube, capacity } => {
+                write!(f, "tube {tube} holds more than {capacity} cells")
+            }
+            PuzzleError::GapBelowFilledCell { tube, cell } => {
+                write!(f, "tube {tube} has an empty cell {cell} below a filled one")
+            }
+            PuzzleError::TooManyColors { colors, tubes } => {
+                write!(f, "{colors} colors do not fit in {tubes} tubes")
+            }
+            PuzzleError::ColorOutOfRange { color, colors } => {
+                write!(f, "color {color} is not one of the {colors} colors")
+            }
+            PuzzleError::HiddenCountMismatch { hidden, missing } => write!(
+                f,
+                "{hidden} hidden cells, but the colors are missing {missing} cells"
+            ),
+            PuzzleError::CellCountMismatch {
+                color,
+                count,
+                height,
+            } => write!(f, "color {color} has {count} cells instead of {height}"),
+        }
+    }
+}
+
+impl Error for PuzzleError {}
+
+/// Checks that `cells` lists whole tubes, filled from the bottom, and that every color fills
+/// exactly one tube.
+///
+/// Tubes and cells in the diagnostics are numbered from 1.
+pub(super) fn validate_puzzle<C: Cell>(height: usize, cells: &[C]) -> Result<(), PuzzleError> {
+    if height == 0 {
+        return Err(PuzzleError::ZeroHeight);
+    }
+    if cells.is_empty() {
+        return Err(PuzzleError::NoTubes);
+    }
+    if !cells.len().is_multiple_of(height) {
+        return Err(PuzzleError::LengthNotMultipleOfHeight {
+            length: cells.len(),
+            height,
+        });
+    }
+    validate_tubes(height, &vec![height; cells.len() / height], cells)
+}
+
+/// Like [`validate_puzzle`] for tubes of individual capacities, stored as many cells apart as the
+/// largest capacity, where only tubes holding at least `height` cells can hold a sorted color.
+pub(super) fn validate_capacities<C: Cell>(
+    height: usize,
+    capacities: &[usize],
+    cells: &[C],
+) -> Result<(), PuzzleError> {
+    if height == 0 {
+        return Err(PuzzleError::ZeroHeight);
+    }
+    if capacities.is_empty() {
+        return Err(PuzzleError::NoTubes);
+    }
+    if let Some(tube) = capacities.iter().position(|&capacity| capacity == 0) {
+        return Err(PuzzleError::ZeroCapacity { tube: tube + 1 });
+    }
+    let stride = capacities.iter().copied().max().unwrap();
+    if cells.len() != stride * capacities.len() {
+        return Err(PuzzleError::LengthMismatch {
+            length: cells.len(),
+            expected: stride * capacities.len(),
+        });
+    }
+    validate_tubes(height, capacities, cells)
+}
+
+fn validate_tubes<C: Cell>(
+    height: usize,
+    capacities: &[usize],
+    cells: &[C],
+) -> Result<(), PuzzleError> {
+    let stride = cells.len() / capacities.len();
+    for (tube, (cells, &capacity)) in cells.chunks_exact(stride).zip(capacities).enumerate() {
+        if let Some(cell) = cells
+            .windows(2)
+            .position(|pair| pair[0] == C::EMPTY && pair[1] != C::EMPTY)
+        {
+            return Err(PuzzleError::GapBelowFilledCell {
+                tube: tube + 1,
+                cell: cell + 1,
+            });
+        }
+        if cells[capacity..].iter().any(|&cell| cell != C::EMPTY) {
+            return Err(PuzzleError::OverCapacity {
+                tube: tube + 1,
+                capacity,
+            });
+        }
+    }
+    let mut counts: Vec<usize> = vec![0; cells.iter().copied().max().map_or(0, C::color) + 1];
+    for &color in cells.iter().filter(|&&color| color != C::EMPTY) {
+        counts[color.color()] += 1;
+    }
+    let colors = counts.iter().filter(|&&count| count > 0).count();
+    let tubes = capacities
+        .iter()
+        .filter(|&&capacity| capacity >= height)
+        .count();
+    if colors > tubes {
+        return Err(PuzzleError::TooManyColors { colors, tubes });
+    }
+    for (color, &count) in counts.iter().enumerate().skip(1) {
+        if count != 0 && count != height {
+            return Err(PuzzleError::CellCountMismatch {
+                color,
+                count,
+                height,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+    use crate::solver::bfs_solver::BFSSolver;
+    use crate::solver::dfs_solver::DFSSolver;
+    use crate::solver::mystery::MysterySession;
+
+    #[test]
+    fn malformed_tubes() {
+        let empty: [u8; 0] = [];
+        assert_eq!(validate_puzzle(0, &[1u8]), Err(PuzzleError::ZeroHeight));
+        assert_eq!(validate_puzzle(2, &empty), Err(PuzzleError::NoTubes));
+        assert_eq!(
+            validate_puzzle(2, &[1u8, 1, 0]),
+            Err(PuzzleError::LengthNotMultipleOfHeight {
+                length: 3,
+                height: 2
+            })
+        );
+        assert_eq!(
+            validate_puzzle(2, &[0u8, 1, 1, 0]),
+            Err(PuzzleError::GapBelowFilledCell { tube: 1, cell: 1 })
+        );
+    }
+
+    #[test]
+    fn malformed_capacities() {
+        let empty: [u8; 0] = [];
+        assert_eq!(
+            validate_capacities(0, &[2], &empty),
+            Err(PuzzleError::ZeroHeight)
+        );
+        assert_eq!(
+            validate_capacities(2, &[], &empty),
+            Err(PuzzleError::NoTubes)
+        );
+        assert_eq!(
+            validate_capacities(2, &[2, 0], &[1u8, 1, 0, 0]),
+            Err(PuzzleError::ZeroCapacity { tube: 2 })
+        );
+        assert_eq!(
+            validate_capacities(2, &[2, 3], &[1u8, 1, 0, 0, 0]),
+            Err(PuzzleError::LengthMismatch {
+                length: 5,
+                expected: 6
+            })
+        );
+        assert_eq!(
+            validate_capacities(2, &[2, 3], &[1u8, 1, 1, 0, 0, 0]),
+            Err(PuzzleError::OverCapacity {
+                tube: 1,
+                capacity: 2
+            })
+        );
+        assert_eq!(
+            validate_capacities(2, &[3, 2], &[1u8, 1, 0, 0, 0, 0]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn impossible_colors() {
+        assert_eq!(
+            validate_puzzle(2, &[1u8, 2, 3, 0]),
+            Err(PuzzleError::TooManyColors {
+                colors: 3,
+                tubes: 2
+            })
+        );
+        // Only tubes holding `height` cells count towards the colors they can sort.
+        assert_eq!(
+            validate_capacities(2, &[2, 1], &[1u8, 2, 0, 0]),
+            Err(PuzzleError::TooManyColors {
+                colors: 2,
+                tubes: 1
+            })
+        );
+        assert_eq!(
+            validate_puzzle(2, &[1u8, 1, 2, 0]),
+            Err(PuzzleError::CellCountMismatch {
+                color: 2,
+                count: 1,
+                height: 2
+            })
+        );
+    }
+
+    #[test]
+    fn mismatched_hidden_cells() {
+        let cells = vec![Some(1u8), Some(3), Some(0), Some(0)];
+        assert_eq!(
+            MysterySession::new(2, 2, cells).err(),
+            Some(PuzzleError::ColorOutOfRange {
+                color: 3,
+                colors: 2
+            })
+        );
+        let cells = vec![None, None, Some(1), Some(1), Some(2), Some(0u8)];
+        assert_eq!(
+            MysterySession::new(2, 2, cells).err(),
+            Some(PuzzleError::HiddenCountMismatch {
+                hidden: 2,
+                missing: 1
+            })
+        );
+    }
+
+    #[test]
+    fn color_filling_two_tubes() {
+        let cells: Vec<u8> = vec![1, 1, 0, 1, 1, 0, 2, 2, 2, 1, 1, 0];
+        let error = PuzzleError::CellCountMismatch {
+            color: 1,
+            count: 6,
+            height: 3,
+        };
+        assert_eq!(validate_puzzle(3, &cells), Err(error));
+        assert_eq!(BFSSolver::try_new(3, cells.clone()).err(), Some(error));
+        assert_eq!(DFSSolver::try_new(3, cells).err(), Some(error));
+    }
+}
@@ -0,0 +1,257 @@
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use rustc_hash::FxHashSet;
+
+use super::cell::Cell;
+use super::game_state::Move;
+use super::packed_state::{PackedState, StatePacker};
+use super::rules::RuleSet;
+use super::search::SearchControl;
+use super::utils::*;
+use super::{SearchConfig, SearchObserver, SearchOutcome, SearchStats, SolutionStep, Solver};
+
+/// Number of states expanded by the first run of [`RestartDFSSolver`], scaled by the Luby
+/// sequence for the following runs.
+pub const DEFAULT_RESTART_BASE: usize = 1 << 12;
+
+/// Number of runs of [`RestartDFSSolver`] once a first solution is known.
+pub const DEFAULT_MAX_RESTARTS: usize = 64;
+
+/// Why a single run stopped before exhausting its subtree.
+enum Stop {
+    Solved,
+    Restart,
+    Outcome(SearchOutcome),
+}
+
+impl From<SearchOutcome> for Stop {
+    fn from(outcome: SearchOutcome) -> Self {
+        Stop::Outcome(outcome)
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    from: usize,
+    to: usize,
+    transform: Vec<usize>,
+}
+
+/// Depth-first search trying the moves of each state in a random order, restarted from scratch
+/// whenever a run expands more states than its share of the Luby schedule.
+///
+/// Runs after the first solution only look for shorter ones, and the shortest solution found is
+/// kept. The search is reproducible for a given seed, across platforms and versions of `rand`, see
+/// [`RestartDFSSolver::set_seed`].
+pub struct RestartDFSSolver<C: Cell = u8> {
+    height: usize,
+    tubes: usize,
+    initial_tubes: Vec<C>,
+    color_tubes: Vec<usize>,
+    seed: u64,
+    restart_base: usize,
+    max_restarts: usize,
+    packer: StatePacker,
+    states: FxHashSet<PackedState>,
+    stack: Vec<State>,
+    run_expanded: usize,
+    run_budget: usize,
+    solution: Option<Vec<Move>>,
+    control: SearchControl,
+}
+
+impl<C: Cell> Solver<C> for RestartDFSSolver<C> {
+    fn new(height: usize, initial_tubes: Vec<C>) -> Self {
+        Self {
+            height,
+            tubes: initial_tubes.len() / height,
+            packer: StatePacker::new(&initial_tubes),
+            color_tubes: get_color_tubes(&initial_tubes, height),
+            initial_tubes,
+            seed: 0,
+            restart_base: DEFAULT_RESTART_BASE,
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            states: FxHashSet::default(),
+            stack: vec![],
+            run_expanded: 0,
+            run_budget: 0,
+            solution: None,
+            control: SearchControl::new(),
+        }
+    }
+
+    fn set_config(&mut self, config: SearchConfig) {
+        self.control.set_config(config);
+    }
+
+    fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.control.set_observer(observer);
+    }
+
+    fn search(&mut self) -> SearchOutcome {
+        self.control.start();
+        self.solution = None;
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let initial_tubes = self.initial_tubes.clone();
+        let mut run = 1;
+        while self.solution.is_none() || run <= self.max_restarts {
+            self.states.clear();
+            self.stack.clear();
+            self.run_expanded = 0;
+            self.run_budget = self.restart_base.saturating_mul(luby(run));
+            match self.inner_search(&initial_tubes, usize::MAX, usize::MAX, &mut rng) {
+                // Without a known solution, the run explored every reachable state.
+                Ok(()) if self.solution.is_none() => break,
+                Ok(()) => {}
+                Err(Stop::Solved) => self.solution = Some(self.get_moves()),
+                Err(Stop::Restart) => {}
+                Err(Stop::Outcome(outcome)) => {
+                    if self.solution.is_some() {
+                        break;
+                    }
+                    return outcome;
+                }
+            }
+            run += 1;
+        }
+        match self.solution {
+            Some(_) => SearchOutcome::Solved,
+            None => SearchOutcome::Unsolvable,
+        }
+    }
+
+    fn get_solution(&self) -> Vec<SolutionStep> {
+        replay_moves(
+            &self.initial_tubes,
+            self.height,
+            self.solution.as_deref().unwrap_or_default(),
+            RuleSet::PourRun,
+        )
+    }
+
+    fn get_stats(&self) -> &SearchStats {
+        self.control.stats()
+    }
+}
+
+impl<C: Cell> RestartDFSSolver<C> {
+    /// Sets the seed of the move shuffling, so that equal seeds give equal searches.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Sets the number of expanded states of a unit run, defaulting to [`DEFAULT_RESTART_BASE`].
+    pub fn set_restart_base(&mut self, base: usize) {
+        self.restart_base = base.max(1);
+    }
+
+    /// Sets the number of runs after which the search stops once a solution is known, defaulting
+    /// to [`DEFAULT_MAX_RESTARTS`].
+    pub fn set_max_restarts(&mut self, restarts: usize) {
+        self.max_restarts = restarts;
+    }
+
+    /// Maps the moves on the stack back to the actual tubes.
+    fn get_moves(&self) -> Vec<Move> {
+        get_moves_from_transforms(
+            self.tubes,
+            self.stack
+                .iter()
+                .map(|state| (state.from, state.to, state.transform.as_slice())),
+        )
+    }
+
+    /// Returns `Err(Stop::Solved)` with a solution shorter than the known one left on the stack,
+    /// or `Ok(())` once the whole subtree has been exhausted.
+    fn inner_search(
+        &mut self,
+        state: &[C],
+        from: usize,
+        to: usize,
+        rng: &mut ChaCha8Rng,
+    ) -> Result<(), Stop> {
+        let (transform, sorted_tubes) = get_transform(state, self.height, self.tubes);
+        if let Some(solution) = &self.solution
+            && self.stack.len() + get_lower_bound(&sorted_tubes, self.height, &self.color_tubes)
+                >= solution.len()
+        {
+            return Ok(());
+        }
+        if !self.states.insert(self.packer.pack(&sorted_tubes)) {
+            self.control.record_duplicates(1);
+            return Ok(());
+        }
+        self.stack.push(State {
+            from,
+            to,
+            transform,
+        });
+        self.control.record_stack_depth(self.stack.len());
+        if is_solved(&sorted_tubes, self.height) {
+            return Err(Stop::Solved);
+        }
+        self.control.expand(1, self.states.len())?;
+        self.run_expanded += 1;
+        if self.run_expanded > self.run_budget {
+            return Err(Stop::Restart);
+        }
+        let tube_stats: Vec<TubeStats> = sorted_tubes
+            .chunks_exact(self.height)
+            .map(|tube| get_tube_stat(tube, self.height))
+            .collect();
+        let mut moves = get_bfs_moves(&tube_stats, self.height, RuleSet::PourRun);
+        moves.shuffle(rng);
+        for (from, to, amount) in moves {
+            let mut tubes = sorted_tubes.clone();
+            pour(&mut tubes, self.height, &tube_stats, from, to, amount);
+            self.inner_search(&tubes, from, to, rng)?;
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// The `i`-th term of the Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, ..., counting from 1.
+fn luby(i: usize) -> usize {
+    let mut i = i;
+    loop {
+        let mut k = 1;
+        while (1 << k) - 1 < i {
+            k += 1;
+        }
+        if i == (1 << k) - 1 {
+            return 1 << (k - 1);
+        }
+        i -= (1 << (k - 1)) - 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(seed: u64) -> (Vec<SolutionStep>, usize) {
+        let tubes = parse_tubes(
+            &[
+                "h b d b", "h a a e", "f c b c", "e g e c", "g g a d", "g d b f", "d f f h",
+                "c a h e", "", "",
+            ],
+            4,
+        );
+        let mut solver = RestartDFSSolver::<u8>::new(4, tubes);
+        solver.set_seed(seed);
+        solver.set_restart_base(64);
+        solver.set_config(SearchConfig {
+            max_expanded_states: Some(20_000),
+            ..SearchConfig::default()
+        });
+        assert!(solver.search().is_solved());
+        (solver.get_solution(), solver.get_stats().expanded_states)
+    }
+
+    #[test]
+    fn same_seed_same_search() {
+        assert_eq!(solve(7), solve(7));
+    }
+}
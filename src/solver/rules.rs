@@ -0,0 +1,19 @@
+/// How many cells of the top color a single move carries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RuleSet {
+    /// As much of the top run as fits into the target, as in water sort games.
+    #[default]
+    PourRun,
+    /// A single cell, as in ball sort games.
+    SingleUnit,
+}
+
+impl RuleSet {
+    /// Number of cells moved out of a top run of `run` cells into a tube with `space` free cells.
+    pub fn amount(self, run: usize, space: usize) -> usize {
+        match self {
+            RuleSet::PourRun => usize::min(run, space),
+            RuleSet::SingleUnit => 1,
+        }
+    }
+}
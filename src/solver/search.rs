@@ -0,0 +1,223 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of expansions between two wall clock checks.
+const CLOCK_INTERVAL: usize = 256;
+
+/// Number of expansions between two progress reports.
+const PROGRESS_INTERVAL: usize = 1 << 16;
+
+/// Limits applied to a single [`super::Solver::search`] call.
+#[derive(Clone, Default)]
+pub struct SearchConfig {
+    pub max_duration: Option<Duration>,
+    pub max_expanded_states: Option<usize>,
+    pub max_visited_states: Option<usize>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// A flag shared between threads to stop a running search.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BudgetReason {
+    Time,
+    ExpandedStates,
+    VisitedStates,
+}
+
+impl fmt::Display for BudgetReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetReason::Time => write!(f, "time limit reached"),
+            BudgetReason::ExpandedStates => write!(f, "expanded state limit reached"),
+            BudgetReason::VisitedStates => write!(f, "visited state limit reached"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchOutcome {
+    Solved,
+    Unsolvable,
+    BudgetExceeded {
+        reason: BudgetReason,
+    },
+    Cancelled,
+    /// An incomplete search ran out of states to explore without finding a solution.
+    Exhausted,
+}
+
+impl SearchOutcome {
+    pub fn is_solved(&self) -> bool {
+        *self == SearchOutcome::Solved
+    }
+}
+
+/// Counters collected while searching.
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    pub expanded_states: usize,
+    /// Generated states rejected because their sorted form had already been visited.
+    pub duplicate_states: usize,
+    /// Number of states first reached at each depth, for breadth-first searches.
+    pub frontier_sizes: Vec<usize>,
+    /// Deepest search stack reached, for depth-first searches.
+    pub max_stack_depth: usize,
+    pub peak_visited_states: usize,
+}
+
+impl SearchStats {
+    /// Adds the counters of a search run after this one.
+    pub(super) fn accumulate(&mut self, other: &SearchStats) {
+        self.expanded_states += other.expanded_states;
+        self.duplicate_states += other.duplicate_states;
+        self.max_stack_depth = self.max_stack_depth.max(other.max_stack_depth);
+        self.peak_visited_states = self.peak_visited_states.max(other.peak_visited_states);
+    }
+}
+
+/// Receives periodic progress reports from a running search.
+pub trait SearchObserver {
+    fn on_progress(&mut self, stats: &SearchStats);
+}
+
+/// Tracks the budget and the statistics of a running search.
+pub(super) struct SearchControl {
+    config: SearchConfig,
+    deadline: Option<Instant>,
+    stats: SearchStats,
+    observer: Option<Box<dyn SearchObserver>>,
+}
+
+impl SearchControl {
+    pub(super) fn new() -> Self {
+        Self {
+            config: SearchConfig::default(),
+            deadline: None,
+            stats: SearchStats::default(),
+            observer: None,
+        }
+    }
+
+    pub(super) fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    pub(super) fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub(super) fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    pub(super) fn start(&mut self) {
+        self.deadline = self
+            .config
+            .max_duration
+            .map(|duration| Instant::now() + duration);
+        self.stats = SearchStats::default();
+    }
+
+    pub(super) fn record_duplicates(&mut self, count: usize) {
+        self.stats.duplicate_states += count;
+    }
+
+    pub(super) fn record_frontier(&mut self, depth: usize, count: usize) {
+        if self.stats.frontier_sizes.len() <= depth {
+            self.stats.frontier_sizes.resize(depth + 1, 0);
+        }
+        self.stats.frontier_sizes[depth] += count;
+    }
+
+    pub(super) fn record_stack_depth(&mut self, depth: usize) {
+        self.stats.max_stack_depth = self.stats.max_stack_depth.max(depth);
+    }
+
+    /// Records `count` expanded states and checks every limit against them.
+    pub(super) fn expand(&mut self, count: usize, visited: usize) -> Result<(), SearchOutcome> {
+        let previous = self.stats.expanded_states;
+        self.stats.expanded_states += count;
+        self.stats.peak_visited_states = self.stats.peak_visited_states.max(visited);
+        if previous / PROGRESS_INTERVAL != self.stats.expanded_states / PROGRESS_INTERVAL
+            && let Some(observer) = self.observer.as_mut()
+        {
+            observer.on_progress(&self.stats);
+        }
+        if self
+            .config
+            .max_expanded_states
+            .is_some_and(|limit| self.stats.expanded_states > limit)
+        {
+            return Err(SearchOutcome::BudgetExceeded {
+                reason: BudgetReason::ExpandedStates,
+            });
+        }
+        if self
+            .config
+            .max_visited_states
+            .is_some_and(|limit| visited > limit)
+        {
+            return Err(SearchOutcome::BudgetExceeded {
+                reason: BudgetReason::VisitedStates,
+            });
+        }
+        if count > 1 || previous / CLOCK_INTERVAL != self.stats.expanded_states / CLOCK_INTERVAL {
+            self.interrupt().check()?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn interrupt(&self) -> Interrupt {
+        Interrupt {
+            cancellation: self.config.cancellation.clone(),
+            deadline: self.deadline,
+        }
+    }
+}
+
+/// The deadline and cancellation token of a search, which can be checked from worker threads.
+#[derive(Clone)]
+pub(super) struct Interrupt {
+    cancellation: Option<CancellationToken>,
+    deadline: Option<Instant>,
+}
+
+impl Interrupt {
+    pub(super) fn check(&self) -> Result<(), SearchOutcome> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(SearchOutcome::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(SearchOutcome::BudgetExceeded {
+                reason: BudgetReason::Time,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use super::SolutionStep;
+use super::cell::Cell;
+use super::game_state::{GameState, Move, MoveError};
+
+/// The first step of a solution that cannot be played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalMove {
+    pub index: usize,
+    pub step: Move,
+    pub error: MoveError,
+}
+
+/// The tubes after playing a whole solution.
+pub struct Simulation<C: Cell = u8> {
+    pub tubes: Vec<C>,
+    pub solved: bool,
+}
+
+/// Plays the steps of a solution returned by [`super::Solver::get_solution`] on `tubes` with the
+/// game rules, each pouring its recorded amount, see [`GameState::apply_step`].
+pub fn simulate<C: Cell>(
+    height: usize,
+    tubes: &[C],
+    steps: &[SolutionStep],
+) -> Result<Simulation<C>, IllegalMove> {
+    simulate_from(GameState::new(height, tubes.to_vec()), steps)
+}
+
+/// Like [`simulate`], starting from `state` with its own rules and tube capacities.
+pub fn simulate_from<C: Cell>(
+    mut state: GameState<C>,
+    steps: &[SolutionStep],
+) -> Result<Simulation<C>, IllegalMove> {
+    for (index, &step) in steps.iter().enumerate() {
+        state.apply_step(step).map_err(|error| IllegalMove {
+            index,
+            step: step.into(),
+            error,
+        })?;
+    }
+    Ok(Simulation {
+        solved: state.is_solved(),
+        tubes: state.cells().to_vec(),
+    })
+}
+
+/// Like [`simulate_from`] for moves without a recorded amount, each pouring as much of the top
+/// color as the rules allow.
+pub fn simulate_moves<C: Cell>(
+    mut state: GameState<C>,
+    moves: &[Move],
+) -> Result<Simulation<C>, IllegalMove> {
+    for (index, &step) in moves.iter().enumerate() {
+        state
+            .apply(step)
+            .map_err(|error| IllegalMove { index, step, error })?;
+    }
+    Ok(Simulation {
+        solved: state.is_solved(),
+        tubes: state.cells().to_vec(),
+    })
+}
+
+/// The cells after each step of a solution returned by [`super::Solver::get_solution`], starting
+/// from `state` with its own rules and tube capacities.
+///
+/// Each step pours its recorded amount, see [`GameState::apply_step`].
+pub fn get_solution_states<C: Cell>(
+    mut state: GameState<C>,
+    steps: &[SolutionStep],
+) -> Result<Vec<Vec<C>>, IllegalMove> {
+    let mut states = Vec::with_capacity(steps.len());
+    for (index, &step) in steps.iter().enumerate() {
+        state.apply_step(step).map_err(|error| IllegalMove {
+            index,
+            step: step.into(),
+            error,
+        })?;
+        states.push(state.cells().to_vec());
+    }
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+    use crate::solver::bfs_solver::BFSSolver;
+    use crate::solver::rules::RuleSet;
+    use crate::solver::utils::parse_tubes;
+
+    fn assert_frames<C: Cell>(state: GameState<C>, steps: &[SolutionStep]) {
+        let states = get_solution_states(state.clone(), steps).unwrap();
+        assert_eq!(states.len(), steps.len());
+        let mut previous = state.cells().to_vec();
+        for (cells, step) in states.iter().zip(steps) {
+            let changed = previous.iter().zip(cells).filter(|(a, b)| a != b).count();
+            assert_eq!(changed, 2 * step.amount);
+            previous = cells.clone();
+        }
+        let mut last = state;
+        for &step in steps {
+            last.apply_step(step).unwrap();
+        }
+        assert!(last.is_solved());
+        assert_eq!(last.cells(), states.last().unwrap().as_slice());
+    }
+
+    #[test]
+    fn recorded_amounts() {
+        let tubes = parse_tubes(&["a b b", "b a a", ""], 3);
+        let mut solver = BFSSolver::new(3, tubes.clone());
+        assert!(solver.search().is_solved());
+        let mut steps = solver.get_solution();
+        assert!(simulate(3, &tubes, &steps).unwrap().solved);
+        steps[1].amount += 1;
+        let illegal = simulate(3, &tubes, &steps).err().unwrap();
+        assert_eq!(illegal.index, 1);
+        assert_eq!(illegal.step, steps[1].into());
+        assert_eq!(illegal.error, MoveError::InvalidAmount);
+    }
+
+    #[test]
+    fn single_unit_frames() {
+        let tubes = parse_tubes(&["a b b", "b a a", ""], 3);
+        let mut solver = BFSSolver::new(3, tubes.clone());
+        solver.set_rules(RuleSet::SingleUnit);
+        assert!(solver.search().is_solved());
+        let steps = solver.get_solution();
+        assert!(steps.iter().all(|step| step.amount == 1));
+        let mut state = GameState::new(3, tubes);
+        state.set_rules(RuleSet::SingleUnit);
+        assert_frames(state, &steps);
+    }
+
+    #[test]
+    fn capacity_frames() {
+        let capacities = vec![3, 3, 5];
+        let tubes = parse_tubes(&["a b a", "b a b", ""], 5);
+        let mut solver = BFSSolver::with_capacities(3, capacities.clone(), tubes.clone());
+        assert!(solver.search().is_solved());
+        assert_frames(
+            GameState::with_capacities(3, capacities, tubes),
+            &solver.get_solution(),
+        );
+    }
+}
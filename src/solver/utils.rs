@@ -1,11 +1,28 @@
-pub(super) fn get_transform(
-    tubes: &[u8],
+use super::SolutionStep;
+use super::cell::Cell;
+use super::constraint::TubeConstraint;
+use super::game_state::{GameState, Move};
+use super::rules::RuleSet;
+
+/// How a depth-first search of a subtree ended, when the budget did not stop it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Subtree {
+    /// A solution was found and left on the stack.
+    Solved,
+    /// Every state of the subtree was searched without finding a solution.
+    Exhausted,
+    /// No solution was found within the bound, the smallest estimate exceeding it given.
+    Exceeded(usize),
+}
+
+pub(super) fn get_transform<C: Cell>(
+    tubes: &[C],
     height: usize,
     tube_count: usize,
-) -> (Vec<usize>, Vec<u8>) {
+) -> (Vec<usize>, Vec<C>) {
     let mut transform: Vec<usize> = (0..tube_count).collect();
     transform.sort_unstable_by_key(|index| &tubes[index * height..(index + 1) * height]);
-    let mut sorted_tubes = vec![0; tube_count * height];
+    let mut sorted_tubes = vec![C::EMPTY; tube_count * height];
     for i in 0..tube_count {
         sorted_tubes[i * height..(i + 1) * height]
             .clone_from_slice(&tubes[transform[i] * height..(transform[i] + 1) * height])
@@ -13,9 +30,54 @@ pub(super) fn get_transform(
     (transform, sorted_tubes)
 }
 
-pub(super) fn is_solved(state: &[u8], height: usize) -> bool {
+/// Like [`get_transform`] for tubes of individual capacities and constraints, where only tubes of
+/// equal capacity and constraint are interchangeable: tubes are sorted by capacity then constraint
+/// first, so sorted states always list them in the same order, see [`sort_tube_kinds`].
+pub(super) fn get_capacity_transform<C: Cell>(
+    tubes: &[C],
+    stride: usize,
+    capacities: &[usize],
+    constraints: &[TubeConstraint],
+) -> (Vec<usize>, Vec<C>) {
+    if capacities.windows(2).all(|pair| pair[0] == pair[1])
+        && constraints.windows(2).all(|pair| pair[0] == pair[1])
+    {
+        return get_transform(tubes, stride, capacities.len());
+    }
+    let mut transform: Vec<usize> = (0..capacities.len()).collect();
+    transform.sort_unstable_by_key(|&index| {
+        (
+            capacities[index],
+            constraints[index],
+            &tubes[index * stride..(index + 1) * stride],
+        )
+    });
+    let mut sorted_tubes = vec![C::EMPTY; tubes.len()];
+    for i in 0..capacities.len() {
+        sorted_tubes[i * stride..(i + 1) * stride]
+            .clone_from_slice(&tubes[transform[i] * stride..(transform[i] + 1) * stride])
+    }
+    (transform, sorted_tubes)
+}
+
+/// The capacities and constraints of the tubes in the order of the sorted states of
+/// [`get_capacity_transform`].
+pub(super) fn sort_tube_kinds(
+    capacities: &[usize],
+    constraints: &[TubeConstraint],
+) -> (Vec<usize>, Vec<TubeConstraint>) {
+    let mut kinds: Vec<(usize, TubeConstraint)> = capacities
+        .iter()
+        .copied()
+        .zip(constraints.iter().copied())
+        .collect();
+    kinds.sort_unstable();
+    kinds.into_iter().unzip()
+}
+
+pub(super) fn is_solved<C: Cell>(state: &[C], height: usize) -> bool {
     state.chunks_exact(height).all(|tube| {
-        if tube[0] == 0 {
+        if tube[0] == C::EMPTY {
             return true;
         }
         for i in 1..height {
@@ -27,41 +89,91 @@ pub(super) fn is_solved(state: &[u8], height: usize) -> bool {
     })
 }
 
+/// Whether every tube of `state`, stored `stride` cells apart, is empty or holds exactly `height`
+/// cells of one color.
+pub(super) fn is_goal<C: Cell>(state: &[C], stride: usize, height: usize) -> bool {
+    state.chunks_exact(stride).all(|tube| {
+        tube[0] == C::EMPTY
+            || (tube.len() >= height
+                && tube[..height].iter().all(|&cell| cell == tube[0])
+                && tube.get(height).is_none_or(|&cell| cell == C::EMPTY))
+    })
+}
+
 pub(super) struct TubeStats {
+    pub(super) capacity: usize,
     pub(super) size: usize,
     pub(super) color_height: usize,
-    pub(super) color: u8,
+    pub(super) color: usize,
     pub(super) simple: bool,
+    pub(super) constraint: TubeConstraint,
 }
 
-pub(super) fn get_tube_stat(tube: &[u8], height: usize) -> TubeStats {
-    if tube[0] == 0 {
+pub(super) fn get_tube_stat<C: Cell>(tube: &[C], height: usize) -> TubeStats {
+    if tube[0] == C::EMPTY {
         return TubeStats {
+            capacity: height,
             size: 0,
             color_height: 0,
             color: 0,
             simple: false,
+            constraint: TubeConstraint::Free,
         };
     }
     let mut size = height;
-    while size > 0 && tube[size - 1] == 0 {
+    while size > 0 && tube[size - 1] == C::EMPTY {
         size -= 1;
     }
     let mut color_height = 1;
-    let color = tube[size - 1];
+    let color = tube[size - 1].color();
     while color_height < size && tube[size - color_height - 1] == tube[size - 1] {
         color_height += 1;
     }
     TubeStats {
+        capacity: height,
         size,
         color_height,
         color,
         simple: color_height == size,
+        constraint: TubeConstraint::Free,
     }
 }
 
-pub(super) fn pour(
-    state: &mut [u8],
+/// Stats of the tubes of `state`, stored `stride` cells apart, where tube `i` holds at most
+/// `capacities[i]` cells under `constraints[i]`.
+pub(super) fn get_capacity_stats<C: Cell>(
+    state: &[C],
+    stride: usize,
+    capacities: &[usize],
+    constraints: &[TubeConstraint],
+) -> Vec<TubeStats> {
+    state
+        .chunks_exact(stride)
+        .zip(capacities.iter().zip(constraints))
+        .map(|(tube, (&capacity, &constraint))| TubeStats {
+            capacity,
+            constraint,
+            ..get_tube_stat(tube, stride)
+        })
+        .collect()
+}
+
+/// Number of tubes holding `height` cells of one color, which unlock
+/// [`TubeConstraint::Locked`] tubes.
+pub(super) fn get_completed_tubes(tube_stats: &[TubeStats], height: usize) -> usize {
+    tube_stats
+        .iter()
+        .filter(|stat| stat.simple && stat.color_height == height)
+        .count()
+}
+
+/// Whether the constraints of both tubes allow pouring `from` into `to`, which must not be empty.
+pub(super) fn can_pour(from: &TubeStats, to: &TubeStats, completed: usize) -> bool {
+    from.constraint.can_pour_from(completed) && to.constraint.can_pour_into(from.color, completed)
+}
+
+pub(super) fn pour<C: Cell>(
+    state: &mut [C],
     height: usize,
     tube_stats: &[TubeStats],
     from: usize,
@@ -70,15 +182,364 @@ pub(super) fn pour(
 ) {
     let from_offset = from * height + tube_stats[from].size;
     let to_offset = to * height + tube_stats[to].size;
-    state[from_offset - amount..from_offset].fill(0);
-    state[to_offset..to_offset + amount].fill(tube_stats[from].color);
+    state[from_offset - amount..from_offset].fill(C::EMPTY);
+    state[to_offset..to_offset + amount].fill(C::from_color(tube_stats[from].color));
 }
 
-pub(super) fn pour_back(state: &mut [u8], height: usize, from: usize, to: usize, amount: usize) {
+pub(super) fn pour_back<C: Cell>(
+    state: &mut [C],
+    height: usize,
+    from: usize,
+    to: usize,
+    amount: usize,
+) {
     let from_stat = get_tube_stat(&state[from * height..(from + 1) * height], height);
     let to_stat = get_tube_stat(&state[to * height..(to + 1) * height], height);
     let from_offset = from * height + from_stat.size;
     let to_offset = to * height + to_stat.size;
-    state[from_offset..from_offset + amount].fill(to_stat.color);
-    state[to_offset - amount..to_offset].fill(0);
+    state[from_offset..from_offset + amount].fill(C::from_color(to_stat.color));
+    state[to_offset - amount..to_offset].fill(C::EMPTY);
+}
+
+pub(super) fn get_color_tubes<C: Cell>(tubes: &[C], height: usize) -> Vec<usize> {
+    let colors = tubes.iter().copied().max().map_or(0, C::color);
+    let mut color_tubes: Vec<usize> = vec![0; colors + 1];
+    for &color in tubes.iter().filter(|&&x| x != C::EMPTY) {
+        color_tubes[color.color()] += 1;
+    }
+    for count in color_tubes.iter_mut() {
+        *count = count.div_ceil(height);
+    }
+    color_tubes
+}
+
+/// Lower bound on the number of pours needed to solve `state`.
+///
+/// A pour changes the number of segments of the poured color by at most one, and can only create
+/// a new bottom segment by emptying into an empty tube, so
+/// `sum(segments - min(bottom segments, goal tubes))` never drops by more than one per move.
+pub(super) fn get_lower_bound<C: Cell>(state: &[C], height: usize, color_tubes: &[usize]) -> usize {
+    let mut segments = vec![0; color_tubes.len()];
+    let mut bottoms = vec![0; color_tubes.len()];
+    for tube in state.chunks_exact(height) {
+        if tube[0] == C::EMPTY {
+            continue;
+        }
+        bottoms[tube[0].color()] += 1;
+        for i in 0..height {
+            if tube[i] == C::EMPTY {
+                break;
+            }
+            if i == 0 || tube[i] != tube[i - 1] {
+                segments[tube[i].color()] += 1;
+            }
+        }
+    }
+    (1..color_tubes.len())
+        .map(|color| segments[color] - usize::min(bottoms[color], color_tubes[color]))
+        .sum()
+}
+
+/// Enumerates every pour on a sorted state that can lead to a new state, as `(from, to, amount)`.
+///
+/// Pours out of completed tubes and pours of a single-colored tube into an empty one are skipped,
+/// and only the first empty tube is used as a target since empty tubes are interchangeable.
+pub(super) fn get_legal_moves(
+    tube_stats: &[TubeStats],
+    height: usize,
+) -> Vec<(usize, usize, usize)> {
+    let empty_tube = tube_stats.iter().position(|stat| stat.size == 0);
+    let mut moves = vec![];
+    for (i, from) in tube_stats.iter().enumerate() {
+        if from.size == 0 || from.color_height == height {
+            continue;
+        }
+        for (j, to) in tube_stats.iter().enumerate() {
+            if j == i || to.size == height {
+                continue;
+            }
+            if to.size == 0 {
+                if from.simple || empty_tube != Some(j) {
+                    continue;
+                }
+            } else if to.color != from.color {
+                continue;
+            }
+            moves.push((i, j, usize::min(from.color_height, height - to.size)));
+        }
+    }
+    moves
+}
+
+/// Enumerates every pour `(from, to, amount)` that could have produced the sorted state, to be
+/// undone with [`pour_back`].
+pub(super) fn get_reverse_moves(
+    tube_stats: &[TubeStats],
+    height: usize,
+) -> Vec<(usize, usize, usize)> {
+    let empty_tube = tube_stats.iter().position(|stat| stat.size == 0);
+    let mut moves = vec![];
+    for (j, to) in tube_stats.iter().enumerate() {
+        for amount in 1..=to.color_height {
+            // The target must have been empty or topped with the same color before the pour.
+            if amount == to.color_height && amount != to.size {
+                continue;
+            }
+            for (i, from) in tube_stats.iter().enumerate() {
+                if i == j || from.size + amount > height {
+                    continue;
+                }
+                if from.size == 0 {
+                    if amount == to.size || empty_tube != Some(i) {
+                        continue;
+                    }
+                } else if from.color == to.color && to.size < height {
+                    // A longer run in the source would have filled the target up.
+                    continue;
+                }
+                moves.push((i, j, amount));
+            }
+        }
+    }
+    moves
+}
+
+pub(super) fn get_solved_state<C: Cell>(
+    tubes: &[C],
+    height: usize,
+    tube_count: usize,
+) -> Option<Vec<C>> {
+    let mut counts: Vec<usize> = vec![0; tubes.iter().copied().max().map_or(0, C::color) + 1];
+    for &color in tubes.iter().filter(|&&x| x != C::EMPTY) {
+        counts[color.color()] += 1;
+    }
+    let mut state = vec![];
+    for (color, &count) in counts.iter().enumerate().skip(1) {
+        if count % height != 0 {
+            return None;
+        }
+        state.extend(std::iter::repeat_n(C::from_color(color), count));
+    }
+    let mut solved = vec![C::EMPTY; tube_count * height - state.len()];
+    solved.extend(state);
+    Some(get_transform(&solved, height, tube_count).1)
+}
+
+/// Recovers the pours between consecutive sorted states, expressed in the tube order of `tubes`.
+pub(super) fn get_steps_between<C: Cell>(
+    tubes: &[C],
+    height: usize,
+    tube_count: usize,
+    states: &[&[C]],
+) -> Vec<SolutionStep> {
+    let mut current = GameState::new(height, tubes.to_vec());
+    for &target in states {
+        current = current
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let mut next = current.clone();
+                next.apply(mv).unwrap();
+                next
+            })
+            .find(|next| get_transform(next.cells(), height, tube_count).1 == target)
+            .unwrap();
+    }
+    current.history().to_vec()
+}
+
+/// Enumerates the pours explored by breadth-first search, as `(from, to, amount)`.
+///
+/// A tube is completed once it holds `height` cells of one color, and the free space of each tube
+/// comes from its capacity and the pours it takes part in from its constraint. When two tubes can
+/// be merged into a (nearly) completed one, that pour is the only move. This only holds when the
+/// whole run is poured and every tube holds `height` cells without constraints, so such moves are
+/// not forced otherwise.
+pub(super) fn get_bfs_moves(
+    tube_stats: &[TubeStats],
+    height: usize,
+    rules: RuleSet,
+) -> Vec<(usize, usize, usize)> {
+    let tubes = tube_stats.len();
+    if rules == RuleSet::PourRun
+        && tube_stats
+            .iter()
+            .all(|stat| stat.capacity == height && stat.constraint == TubeConstraint::Free)
+        && let Some(forced) = get_forced_move(tube_stats, height)
+    {
+        return vec![forced];
+    }
+    let completed = |stat: &TubeStats| stat.simple && stat.color_height == height;
+    let completed_tubes = get_completed_tubes(tube_stats, height);
+    let mut moves = vec![];
+    for i in 0..tubes {
+        if completed(&tube_stats[i]) {
+            continue;
+        } else if tube_stats[i].size == 0 {
+            // Empty tubes of equal capacity and constraint are interchangeable and sorted next to
+            // each other.
+            if i > 0
+                && tube_stats[i - 1].size == 0
+                && tube_stats[i - 1].capacity == tube_stats[i].capacity
+                && tube_stats[i - 1].constraint == tube_stats[i].constraint
+            {
+                continue;
+            }
+            for (j, stat) in tube_stats.iter().enumerate() {
+                // Moving a single-colored tube only matters into a different kind of tube.
+                if j == i
+                    || stat.size == 0
+                    || (stat.simple
+                        && stat.capacity == tube_stats[i].capacity
+                        && stat.constraint == tube_stats[i].constraint)
+                    || !can_pour(stat, &tube_stats[i], completed_tubes)
+                {
+                    continue;
+                }
+                moves.push((
+                    j,
+                    i,
+                    rules.amount(stat.color_height, tube_stats[i].capacity),
+                ));
+            }
+        } else {
+            for j in (i + 1)..tubes {
+                if !completed(&tube_stats[j]) && tube_stats[i].color == tube_stats[j].color {
+                    let mut indexes = vec![];
+                    if tube_stats[j].size < tube_stats[j].capacity {
+                        indexes.push((i, j));
+                    }
+                    if tube_stats[i].size < tube_stats[i].capacity {
+                        indexes.push((j, i));
+                    }
+                    for (x, y) in indexes {
+                        if !can_pour(&tube_stats[x], &tube_stats[y], completed_tubes) {
+                            continue;
+                        }
+                        let amount = rules.amount(
+                            tube_stats[x].color_height,
+                            tube_stats[y].capacity - tube_stats[y].size,
+                        );
+                        moves.push((x, y, amount));
+                    }
+                }
+            }
+        }
+    }
+    moves
+}
+
+/// The pour merging two single-colored tubes into a (nearly) completed one, or completing a
+/// single-colored tube with the top run of another, which can always be played first.
+fn get_forced_move(tube_stats: &[TubeStats], height: usize) -> Option<(usize, usize, usize)> {
+    let tubes = tube_stats.len();
+    for i in 0..(tubes - 1) {
+        if !tube_stats[i].simple || tube_stats[i].color_height == height {
+            continue;
+        }
+        for j in (i + 1)..tubes {
+            if tube_stats[j].simple
+                && tube_stats[i].color == tube_stats[j].color
+                && tube_stats[i].color_height + tube_stats[j].color_height >= height - 1
+            {
+                return Some((i, j, tube_stats[i].color_height));
+            }
+        }
+    }
+    for i in 0..tubes {
+        if !tube_stats[i].simple || tube_stats[i].color_height == height {
+            continue;
+        }
+        for j in 0..tubes {
+            if j == i || tube_stats[j].simple || tube_stats[i].color != tube_stats[j].color {
+                continue;
+            }
+            let amount = tube_stats[j].color_height;
+            if tube_stats[i].color_height + amount == height {
+                return Some((j, i, amount));
+            }
+        }
+    }
+    None
+}
+
+/// Maps a path of pours back to the initial tube order, from the pour leading to each state, in
+/// the sorted order of its parent, and the transform sorting the state. The first state is the
+/// initial one, whose pour is ignored.
+pub(super) fn get_moves_from_transforms<'a>(
+    tubes: usize,
+    path: impl IntoIterator<Item = (usize, usize, &'a [usize])>,
+) -> Vec<Move> {
+    let mut moves = vec![];
+    let mut transform: Vec<usize> = (0..tubes).collect();
+    let mut new_transform = vec![0; tubes];
+    for (index, (from, to, state_transform)) in path.into_iter().enumerate() {
+        if index > 0 {
+            moves.push(Move {
+                from: transform[from],
+                to: transform[to],
+            });
+        }
+        for i in 0..tubes {
+            new_transform[i] = transform[state_transform[i]];
+        }
+        std::mem::swap(&mut transform, &mut new_transform);
+    }
+    moves
+}
+
+/// Replays pours given in sorted tube order onto `initial`, recovering the actual tube indexes.
+pub(super) fn get_steps_from_moves<C: Cell>(
+    initial: GameState<C>,
+    moves: &[(usize, usize, usize)],
+) -> Vec<SolutionStep> {
+    let mut current = initial;
+    for &(from, to, _) in moves {
+        let transform = current.sorted_transform();
+        current
+            .apply(Move {
+                from: transform[from],
+                to: transform[to],
+            })
+            .unwrap();
+    }
+    current.history().to_vec()
+}
+
+/// Plays pours given in the tube order of `tubes`, filling in the amount and color of each step.
+pub(super) fn replay_moves<C: Cell>(
+    tubes: &[C],
+    height: usize,
+    moves: &[Move],
+    rules: RuleSet,
+) -> Vec<SolutionStep> {
+    let mut current = GameState::new(height, tubes.to_vec());
+    current.set_rules(rules);
+    for &mv in moves {
+        current.apply(mv).unwrap();
+    }
+    current.history().to_vec()
+}
+
+/// Lists the cells of one tube per string of space-separated colors, bottom first and stored
+/// `stride` cells apart, numbering the colors from 1 in order of appearance.
+#[cfg(test)]
+pub(super) fn parse_tubes(tubes: &[&str], stride: usize) -> Vec<u8> {
+    let mut colors: Vec<&str> = vec![];
+    let mut cells = vec![];
+    for tube in tubes {
+        let start = cells.len();
+        for color in tube.split_ascii_whitespace() {
+            let number = match colors.iter().position(|&known| known == color) {
+                Some(index) => index + 1,
+                None => {
+                    colors.push(color);
+                    colors.len()
+                }
+            };
+            cells.push(number as u8);
+        }
+        cells.resize(start + stride, 0);
+    }
+    cells
 }